@@ -0,0 +1,60 @@
+//! Finished games saved as PGN, either one file per game or all of them appended to
+//! a single database file.
+use crate::pgn::PgnGame;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How games are laid out in the archive directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveMode {
+    /// `<game id>.pgn` for every game.
+    FilePerGame,
+    /// Every game appended to this file in the directory, e.g. "games.pgn".
+    Database(String),
+}
+
+pub struct GameArchive {
+    dir: PathBuf,
+    mode: ArchiveMode,
+    // Games finishing at the same time must not interleave in the database file.
+    write_lock: Mutex<()>,
+}
+
+impl GameArchive {
+    /// An archive in `dir`, which is created when the first game is saved.
+    pub fn new(dir: impl AsRef<Path>, mode: ArchiveMode) -> Self {
+        GameArchive {
+            dir: dir.as_ref().to_path_buf(),
+            mode,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Save `game` and return the file it went to. Saving a game with the same id
+    /// again replaces it when there is a file per game.
+    pub fn save(&self, game_id: &str, game: &PgnGame) -> io::Result<PathBuf> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        fs::create_dir_all(&self.dir)?;
+        match &self.mode {
+            ArchiveMode::FilePerGame => {
+                // Lichess ids are alphanumeric, but don't let anything else escape the directory.
+                let name: String = game_id.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+                let path = self.dir.join(format!("{name}.pgn"));
+                fs::write(&path, game.to_string())?;
+                Ok(path)
+            }
+            ArchiveMode::Database(file) => {
+                let path = self.dir.join(file);
+                let mut database = OpenOptions::new().create(true).append(true).open(&path)?;
+                database.write_all(game.to_string().as_bytes())?;
+                Ok(path)
+            }
+        }
+    }
+}
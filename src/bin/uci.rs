@@ -0,0 +1,692 @@
+//! UCI frontend, so tinychess can be used from cutechess-cli, Arena or any other GUI
+use chess::{Board, Color};
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tinychess::bench::{run_bench, BENCH_POSITIONS, DEFAULT_BENCH_DEPTH};
+use tinychess::book::OpeningBook;
+use tinychess::epd::{run_position, EpdPosition};
+use tinychess::engine::{MATE_BOUND, MATE_SCORE};
+use tinychess::notation::{apply_uci_moves, format_move_as_san, format_move_as_uci, format_moves_as_uci};
+use tinychess::perft::divide;
+use tinychess::selfplay::{run_match, MatchScore, MatchSettings, Player, Sprt, SprtResult};
+use tinychess::syzygy::Tablebases;
+use tinychess::{
+    Clock, Engine, EngineConfig, GameHistory, PonderHit, SearchInfo, SearchLimits, TimeBudget, TimeManager,
+};
+
+// Default time we keep in reserve for GUI/network lag, in milliseconds.
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 50;
+
+// Book moves are picked in proportion to their weights.
+const BOOK_RANDOMNESS: f64 = 1.0;
+
+struct Uci {
+    board: Board,
+    // The game before `board`, for draw detection.
+    history: GameHistory,
+    // Full moves played before `board`, for the time manager.
+    moves_played: u32,
+    time_manager: TimeManager,
+    book: Option<OpeningBook>,
+    engine: Arc<Engine>,
+    // Stop flag of the running search and the thread that reports its result.
+    search: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+    // Set while the running search ponders, `ponderhit` makes it a normal one.
+    ponder: Option<Arc<PonderHit>>,
+}
+
+/// Everything that can follow `go`.
+#[derive(Debug, Default)]
+struct GoParams {
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+    movestogo: Option<u64>,
+    movetime: Option<u64>,
+    depth: Option<u32>,
+    nodes: Option<u64>,
+    infinite: bool,
+    ponder: bool,
+    perft: Option<u32>,
+}
+
+fn main() {
+    // `uci perft <depth> [<fen>]` checks move generation, `uci bench [<depth>]` the
+    // search and `uci epd <file> [movetime <ms>] [depth <n>]` its tactics, without a GUI.
+    // `uci match [<option> <value>]...` plays two configurations against each other.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("bench") {
+        print_bench(args.get(1).and_then(|d| d.parse().ok()));
+        return;
+    }
+    if args.first().map(String::as_str) == Some("match") {
+        run_match_command(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("epd") {
+        run_epd(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("perft") {
+        let depth = args.get(1).and_then(|d| d.parse().ok()).unwrap_or(5);
+        let board = match args.get(2..).filter(|fen| !fen.is_empty()) {
+            Some(fen) => match Board::from_str(&fen.join(" ")) {
+                Ok(board) => board,
+                Err(_) => {
+                    eprintln!("Invalid FEN: {}", fen.join(" "));
+                    std::process::exit(1);
+                }
+            },
+            None => Board::default(),
+        };
+        print_divide(&board, depth);
+        return;
+    }
+
+    let mut uci = Uci {
+        board: Board::default(),
+        history: GameHistory::default(),
+        moves_played: 0,
+        time_manager: TimeManager::new(Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS)),
+        book: None,
+        engine: Arc::new(Engine::new(EngineConfig::default())),
+        search: None,
+        ponder: None,
+    };
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if !uci.handle_command(&line) {
+            break;
+        }
+    }
+    uci.stop_search();
+}
+
+impl Uci {
+    /// Handle one line from the GUI. Returns false when we should exit.
+    fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                println!("id name tinychess");
+                println!("id author deja-vous");
+                let defaults = EngineConfig::default();
+                println!("option name Hash type spin default {} min 1 max 4096", defaults.hash_size_mb);
+                println!("option name Threads type spin default {} min 0 max 256", defaults.threads);
+                println!("option name Contempt type spin default {} min -1000 max 1000", defaults.contempt);
+                println!("option name BookFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD_MS} min 0 max 5000");
+                // GUIs only send `go ponder` to engines that have this option.
+                println!("option name Ponder type check default false");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.engine.new_game();
+                self.board = Board::default();
+                self.history = GameHistory::default();
+                self.moves_played = 0;
+            }
+            Some("setoption") => {
+                self.stop_search();
+                self.set_option(&tokens.collect::<Vec<_>>());
+            }
+            Some("position") => {
+                self.stop_search();
+                match parse_position(&tokens.collect::<Vec<_>>()) {
+                    Some((board, history, moves_played)) => {
+                        self.board = board;
+                        self.history = history;
+                        self.moves_played = moves_played;
+                    }
+                    None => eprintln!("Invalid position command: {line}"),
+                }
+            }
+            Some("go") => {
+                self.stop_search();
+                self.go(parse_go(&tokens.collect::<Vec<_>>()));
+            }
+            Some("stop") => self.stop_search(),
+            Some("ponderhit") => match &self.ponder {
+                Some(ponder) => ponder.hit(),
+                None => eprintln!("ponderhit without a ponder search"),
+            },
+            Some("bench") => {
+                self.stop_search();
+                print_bench(tokens.next().and_then(|d| d.parse().ok()));
+            }
+            Some("quit") => return false,
+            Some(other) => eprintln!("Unknown command: {other}"),
+            None => {}
+        }
+        true
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        // setoption name <name> value <value>
+        let name_pos = args.iter().position(|&t| t == "name");
+        let value_pos = args.iter().position(|&t| t == "value");
+        let (Some(name_pos), Some(value_pos)) = (name_pos, value_pos) else {
+            return;
+        };
+        let name = args[name_pos + 1..value_pos].join(" ");
+        let value = args[value_pos + 1..].join(" ");
+
+        if name.eq_ignore_ascii_case("BookFile") {
+            self.book = None;
+            if !value.is_empty() && value != "<empty>" {
+                match OpeningBook::open(&value) {
+                    Ok(book) => self.book = Some(book),
+                    Err(e) => eprintln!("Could not load book {value}: {e}"),
+                }
+            }
+            return;
+        }
+
+        // We ponder whenever the GUI says `go ponder`, nothing to set up.
+        if name.eq_ignore_ascii_case("Ponder") {
+            return;
+        }
+
+        if name.eq_ignore_ascii_case("Move Overhead") {
+            match value.parse::<u64>() {
+                Ok(ms) => self.time_manager.move_overhead = Duration::from_millis(ms),
+                _ => eprintln!("Invalid Move Overhead value: {value}"),
+            }
+            return;
+        }
+
+        // No search is running, so nobody else holds the engine.
+        let engine = Arc::get_mut(&mut self.engine).expect("engine is still in use");
+
+        if name.eq_ignore_ascii_case("SyzygyPath") {
+            engine.set_tablebases(None);
+            if !value.is_empty() && value != "<empty>" {
+                match Tablebases::open(&value) {
+                    Ok(tablebases) => {
+                        println!("info string found {} tablebases", tablebases.len());
+                        engine.set_tablebases(Some(tablebases));
+                    }
+                    Err(e) => eprintln!("Could not load tablebases from {value}: {e}"),
+                }
+            }
+            return;
+        }
+
+        let mut config = engine.config().clone();
+        if name.eq_ignore_ascii_case("Hash") {
+            match value.parse::<usize>() {
+                Ok(mb) if mb > 0 => config.hash_size_mb = mb,
+                _ => eprintln!("Invalid Hash value: {value}"),
+            }
+        } else if name.eq_ignore_ascii_case("Contempt") {
+            match value.parse::<i32>() {
+                Ok(contempt) => config.contempt = contempt,
+                _ => eprintln!("Invalid Contempt value: {value}"),
+            }
+        } else if name.eq_ignore_ascii_case("Threads") {
+            match value.parse::<usize>() {
+                Ok(threads) => config.threads = threads,
+                _ => eprintln!("Invalid Threads value: {value}"),
+            }
+        } else {
+            eprintln!("Unknown option: {name}");
+        }
+        engine.configure(config);
+    }
+
+    fn go(&mut self, params: GoParams) {
+        if let Some(depth) = params.perft {
+            print_divide(&self.board, depth);
+            return;
+        }
+
+        // Book moves are instant, unless the GUI wants us to analyse (or to ponder,
+        // when it waits for `ponderhit` before taking our move).
+        if !params.infinite && !params.ponder {
+            let book_move = self.book.as_ref().and_then(|b| b.choose_move(&self.board, BOOK_RANDOMNESS));
+            if let Some(mv) = book_move {
+                println!("bestmove {}", format_move_as_uci(mv));
+                return;
+            }
+        }
+
+        self.ponder = params.ponder.then(|| Arc::new(PonderHit::default()));
+        let limits = SearchLimits {
+            max_depth: params.depth.unwrap_or(SearchLimits::default().max_depth),
+            time: self.allocate_time(&params),
+            max_nodes: params.nodes,
+            ponder: self.ponder.clone(),
+        };
+        let infinite = params.infinite;
+        let ponder = self.ponder.clone();
+
+        let handle = self.engine.start_search(self.board, self.history.clone(), limits, print_info);
+        let stop = handle.stop_flag();
+        let thread_stop = Arc::clone(&stop);
+
+        let reporter = thread::spawn(move || {
+            let best = handle.wait();
+
+            // In infinite mode the GUI expects nothing until it sends `stop`, and while
+            // pondering nothing until `stop` or `ponderhit`.
+            let pondering = || ponder.as_ref().is_some_and(|p| !p.is_hit());
+            while (infinite || pondering()) && !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            match best {
+                // The reply we expect, for the GUI to ponder on
+                Some(result) if result.pv.len() > 1 => println!(
+                    "bestmove {} ponder {}",
+                    format_move_as_uci(result.best_move),
+                    format_move_as_uci(result.pv[1])
+                ),
+                Some(result) => println!("bestmove {}", format_move_as_uci(result.best_move)),
+                None => println!("bestmove 0000"),
+            }
+        });
+        self.search = Some((stop, reporter));
+    }
+
+    /// Decide how long to think. `None` means until depth/nodes/stop.
+    fn allocate_time(&self, params: &GoParams) -> Option<TimeBudget> {
+        if params.infinite {
+            return None;
+        }
+        if let Some(movetime) = params.movetime {
+            let overhead = self.time_manager.move_overhead.as_millis() as u64;
+            return Some(TimeBudget::fixed(Duration::from_millis(
+                movetime.saturating_sub(overhead).max(1),
+            )));
+        }
+        let (remaining, inc) = match self.board.side_to_move() {
+            Color::White => (params.wtime?, params.winc.unwrap_or(0)),
+            Color::Black => (params.btime?, params.binc.unwrap_or(0)),
+        };
+        let clock = Clock {
+            remaining: Duration::from_millis(remaining),
+            increment: Duration::from_millis(inc),
+            moves_to_go: params.movestogo.map(|n| n as u32),
+        };
+        Some(self.time_manager.allocate(&clock, self.moves_played))
+    }
+
+    fn stop_search(&mut self) {
+        self.ponder = None;
+        if let Some((stop, reporter)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = reporter.join();
+        }
+    }
+}
+
+/// Perft split by root move, in the same format as Stockfish's `go perft`.
+fn print_divide(board: &Board, depth: u32) {
+    let start = Instant::now();
+    let moves = divide(board, depth);
+    for (mv, nodes) in &moves {
+        println!("{}: {nodes}", format_move_as_uci(*mv));
+    }
+    let total: u64 = moves.iter().map(|(_, nodes)| nodes).sum();
+    println!();
+    println!("Nodes searched: {total}");
+    eprintln!("Time: {} ms", start.elapsed().as_millis());
+}
+
+/// Run the bench and print its node count, the signature to compare between commits.
+fn print_bench(depth: Option<u32>) {
+    let depth = depth.unwrap_or(DEFAULT_BENCH_DEPTH);
+    let result = run_bench(depth, |i, info| {
+        println!(
+            "Position {}/{}: {} nodes, bestmove {} ({})",
+            i + 1,
+            BENCH_POSITIONS.len(),
+            info.nodes,
+            format_move_as_uci(info.best_move),
+            BENCH_POSITIONS[i]
+        );
+    });
+    println!();
+    println!("Depth          : {depth}");
+    println!("Total time (ms): {}", result.elapsed.as_millis());
+    println!("Nodes searched : {}", result.nodes);
+    println!("Nodes/second   : {}", result.nps());
+}
+
+/// Run an EPD test suite, one position at a time, and print how many were solved.
+fn run_epd(args: &[String]) {
+    let Some(path) = args.first() else {
+        eprintln!("Usage: uci epd <file> [movetime <ms>] [depth <n>]");
+        std::process::exit(1);
+    };
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Could not read {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let options: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+    // One second per position unless told otherwise.
+    let limits = fixed_limits(&parse_go(&options), 1000);
+
+    let engine = Engine::new(EngineConfig::default());
+    // Solved, total, and the sum of the times to solution, per group of ids.
+    let mut groups: Vec<(String, u32, u32, Duration)> = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let epd = match line.parse::<EpdPosition>() {
+            Ok(epd) => epd,
+            Err(e) => {
+                eprintln!("Skipping line {}: {e}", number + 1);
+                continue;
+            }
+        };
+        let id = epd.id.clone().unwrap_or_else(|| format!("line {}", number + 1));
+
+        engine.new_game();
+        let result = run_position(&engine, &epd, &limits);
+        let played = result
+            .info
+            .as_ref()
+            .map_or("none".to_string(), |info| format_move_as_san(&epd.board, info.best_move));
+        let mut expected: Vec<String> =
+            epd.best_moves.iter().map(|&mv| format_move_as_san(&epd.board, mv)).collect();
+        expected.extend(epd.avoid_moves.iter().map(|&mv| format!("not {}", format_move_as_san(&epd.board, mv))));
+        if result.solved {
+            let depth = result.depth_to_solution.unwrap_or(0);
+            let ms = result.time_to_solution.unwrap_or_default().as_millis();
+            println!("{id:<12} solved  {played:<8} depth {depth:<3} {ms:>6} ms");
+        } else {
+            println!("{id:<12} FAILED  {played:<8} expected {}", expected.join(", "));
+        }
+
+        // STS style ids ("STS1.001") are grouped by theme, everything else by file.
+        let group = epd.id.as_ref().and_then(|id| id.split_once('.')).map_or("", |(group, _)| group);
+        let index = match groups.iter().position(|(name, ..)| name == group) {
+            Some(index) => index,
+            None => {
+                groups.push((group.to_string(), 0, 0, Duration::ZERO));
+                groups.len() - 1
+            }
+        };
+        let entry = &mut groups[index];
+        entry.2 += 1;
+        if result.solved {
+            entry.1 += 1;
+            entry.3 += result.time_to_solution.unwrap_or_default();
+        }
+    }
+
+    let solved: u32 = groups.iter().map(|group| group.1).sum();
+    let total: u32 = groups.iter().map(|group| group.2).sum();
+    let time: Duration = groups.iter().map(|group| group.3).sum();
+    println!();
+    println!("{:<12} {:>7} {:>7} {:>7} {:>10}", "suite", "solved", "total", "%", "avg ms");
+    if groups.len() > 1 {
+        for (name, solved, total, time) in &groups {
+            print_epd_row(name, *solved, *total, *time);
+        }
+    }
+    print_epd_row("all", solved, total, time);
+}
+
+/// Limits for searching without a clock: `movetime`, `depth` and `nodes` as given,
+/// and `default_ms` per move if neither a time nor another limit is.
+fn fixed_limits(params: &GoParams, default_ms: u64) -> SearchLimits {
+    let unlimited = params.depth.is_none() && params.nodes.is_none();
+    SearchLimits {
+        max_depth: params.depth.unwrap_or(SearchLimits::default().max_depth),
+        time: match params.movetime {
+            Some(ms) => Some(TimeBudget::fixed(Duration::from_millis(ms))),
+            None if unlimited => Some(TimeBudget::fixed(Duration::from_millis(default_ms))),
+            None => None,
+        },
+        max_nodes: params.nodes,
+        ..Default::default()
+    }
+}
+
+fn print_epd_row(name: &str, solved: u32, total: u32, time_to_solution: Duration) {
+    let percent = 100.0 * solved as f64 / total.max(1) as f64;
+    let average = time_to_solution.as_millis() / solved.max(1) as u128;
+    println!("{name:<12} {solved:>7} {total:>7} {percent:>6.1}% {average:>10}");
+}
+
+/// Play two configurations of the engine against each other, e.g.
+/// `uci match games 2000 movetime 50 b.contempt 20`. Options prefixed with `a.` or
+/// `b.` only apply to that player, the others to both.
+fn run_match_command(args: &[String]) {
+    let mut settings = MatchSettings {
+        concurrency: thread::available_parallelism().map_or(1, |n| n.get()),
+        sprt: Some(Sprt::default()),
+        ..Default::default()
+    };
+    let mut games = None;
+    let mut sprt = Sprt::default();
+    let mut use_sprt = true;
+    // Small tables and one thread each, the games run in parallel instead.
+    let mut configs = [0, 1].map(|_| EngineConfig {
+        hash_size_mb: 16,
+        threads: 1,
+        contempt: 0,
+    });
+    let mut params = [GoParams::default(), GoParams::default()];
+
+    for pair in args.chunks(2) {
+        let [key, value] = pair else {
+            eprintln!("Missing value for {}", pair[0]);
+            std::process::exit(1);
+        };
+        let (sides, option) = match key.split_once('.') {
+            Some(("a", option)) => (0..1, option),
+            Some(("b", option)) => (1..2, option),
+            _ => (0..2, key.as_str()),
+        };
+        let number = |value: &str| -> f64 {
+            value.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid value for {key}: {value}");
+                std::process::exit(1);
+            })
+        };
+        match option {
+            "games" => games = Some(number(value) as usize),
+            "concurrency" => settings.concurrency = number(value) as usize,
+            "maxplies" => settings.max_plies = number(value) as u32,
+            "openings" => settings.openings = read_openings(value),
+            "elo0" => sprt.elo0 = number(value),
+            "elo1" => sprt.elo1 = number(value),
+            "alpha" => sprt.alpha = number(value),
+            "beta" => sprt.beta = number(value),
+            "sprt" => use_sprt = value != "off",
+            "movetime" => sides.for_each(|side| params[side].movetime = Some(number(value) as u64)),
+            "depth" => sides.for_each(|side| params[side].depth = Some(number(value) as u32)),
+            "nodes" => sides.for_each(|side| params[side].nodes = Some(number(value) as u64)),
+            "hash" => sides.for_each(|side| configs[side].hash_size_mb = number(value) as usize),
+            "threads" => sides.for_each(|side| configs[side].threads = number(value) as usize),
+            "contempt" => sides.for_each(|side| configs[side].contempt = number(value) as i32),
+            _ => {
+                eprintln!("Unknown option {key}");
+                std::process::exit(1);
+            }
+        }
+    }
+    settings.games = games.unwrap_or(settings.openings.len() * 2);
+    settings.sprt = use_sprt.then_some(sprt);
+
+    let [a, b] = [0, 1].map(|side| Player {
+        name: ["A", "B"][side].to_string(),
+        config: configs[side].clone(),
+        limits: fixed_limits(&params[side], 100),
+    });
+    println!(
+        "{} games of {} vs {} from {} openings, {} at a time",
+        settings.games,
+        a.name,
+        b.name,
+        settings.openings.len(),
+        settings.concurrency
+    );
+
+    let score = run_match(&a, &b, &settings, |game, score| {
+        let (white, black) = match game.first_is_white {
+            true => (&a.name, &b.name),
+            false => (&b.name, &a.name),
+        };
+        println!(
+            "Game {} ({white} vs {black}, opening {}): {} {{{}}}",
+            game.index + 1,
+            game.index / 2 % settings.openings.len() + 1,
+            game.outcome.as_str(),
+            game.termination.as_str()
+        );
+        print_match_score(score, settings.sprt.as_ref());
+    });
+
+    println!();
+    println!("Finished: {} vs {}", a.name, b.name);
+    print_match_score(&score, settings.sprt.as_ref());
+    if let Some(sprt) = &settings.sprt {
+        match sprt.result(&score) {
+            SprtResult::H1 => println!("SPRT: H1 accepted, {} is stronger", a.name),
+            SprtResult::H0 => println!("SPRT: H0 accepted, {} is not stronger", a.name),
+            SprtResult::Continue => println!("SPRT: no result yet, play more games"),
+        }
+    }
+}
+
+fn print_match_score(score: &MatchScore, sprt: Option<&Sprt>) {
+    let (elo, margin) = score.elo();
+    print!(
+        "Score: {} - {} - {} [{:.3}] {} games, Elo {elo:.1} +/- {margin:.1}",
+        score.wins,
+        score.losses,
+        score.draws,
+        score.score(),
+        score.games()
+    );
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        print!(", LLR {:.2} ({lower:.2}, {upper:.2}) [{}, {}]", sprt.llr(score), sprt.elo0, sprt.elo1);
+    }
+    println!();
+}
+
+/// Starting positions for a match, one FEN or EPD line each.
+fn read_openings(path: &str) -> Vec<Board> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Could not read {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+    let mut openings = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Only the position: no move counters or EPD operations.
+        let fen = line.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        match Board::from_str(&fen) {
+            Ok(board) => openings.push(board),
+            Err(_) => eprintln!("Skipping line {}: invalid position {fen:?}", number + 1),
+        }
+    }
+    openings
+}
+
+fn print_info(info: &SearchInfo) {
+    let ms = info.elapsed.as_millis() as u64;
+    let nps = info.nodes * 1000 / ms.max(1);
+    println!(
+        "info depth {} score {} nodes {} nps {} tbhits {} time {} pv {}",
+        info.depth,
+        format_score(info.score),
+        info.nodes,
+        nps,
+        info.tb_hits,
+        ms,
+        format_moves_as_uci(&info.pv)
+    );
+}
+
+fn format_score(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {score}")
+    }
+}
+
+/// Parse `startpos [moves ...]` or `fen <fen> [moves ...]`, returning the board,
+/// the game that led to it and how many full moves were played to reach it.
+fn parse_position(args: &[&str]) -> Option<(Board, GameHistory, u32)> {
+    let moves_pos = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+    let mut history = GameHistory::default();
+    let (mut board, mut plies) = match args.first() {
+        Some(&"startpos") => (Board::default(), 0),
+        Some(&"fen") => {
+            let fen = &args[1..moves_pos];
+            let board = Board::from_str(&fen.join(" ")).ok()?;
+            // The halfmove clock and fullmove number are the 5th and 6th FEN fields.
+            history.halfmove_clock = fen.get(4).and_then(|n| n.parse::<u32>().ok()).unwrap_or(0);
+            let fullmove = fen.get(5).and_then(|n| n.parse::<u32>().ok()).unwrap_or(1);
+            let black_to_move = board.side_to_move() == Color::Black;
+            (board, fullmove.saturating_sub(1) * 2 + black_to_move as u32)
+        }
+        _ => return None,
+    };
+    if moves_pos < args.len() {
+        let moves = &args[moves_pos + 1..];
+        apply_uci_moves(&mut board, &mut history, &moves.join(" ")).ok()?;
+        plies += moves.len() as u32;
+    }
+    Some((board, history, plies / 2))
+}
+
+/// A time in milliseconds, where GUIs send a negative clock for a side that is
+/// over its time.
+fn millis(value: Option<&&str>) -> Option<u64> {
+    value.and_then(|v| v.parse::<i64>().ok()).map(|ms| ms.max(0) as u64)
+}
+
+fn parse_go(args: &[&str]) -> GoParams {
+    let mut params = GoParams::default();
+    let mut iter = args.iter();
+    while let Some(&token) = iter.next() {
+        let mut value = || iter.next().and_then(|v| v.parse::<u64>().ok());
+        match token {
+            "wtime" => params.wtime = millis(iter.next()),
+            "btime" => params.btime = millis(iter.next()),
+            "winc" => params.winc = millis(iter.next()),
+            "binc" => params.binc = millis(iter.next()),
+            "movetime" => params.movetime = millis(iter.next()),
+            "movestogo" => params.movestogo = value(),
+            "depth" => params.depth = value().map(|d| d as u32),
+            "nodes" => params.nodes = value(),
+            "infinite" => params.infinite = true,
+            "ponder" => params.ponder = true,
+            "perft" => params.perft = value().map(|d| d as u32),
+            _ => {}
+        }
+    }
+    params
+}
@@ -0,0 +1,164 @@
+use chess::{Board, ChessMove, Color, MoveGen, Piece, Square, ALL_SQUARES};
+use crate::polyglot_random::POLYGLOT_RANDOM;
+use rand::Rng;
+use std::{fs, io, path::Path};
+
+// Offsets into POLYGLOT_RANDOM
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+/// One move of a Polyglot book, 16 bytes on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+}
+
+/// An opening book in the Polyglot `.bin` format.
+pub struct OpeningBook {
+    // Sorted by key, as in the file
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    /// Read a whole book into memory.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if !bytes.len().is_multiple_of(16) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "polyglot book size is not a multiple of 16 bytes",
+            ));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(16)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                // bytes 12..16 are the "learn" field, which nobody uses
+            })
+            .collect();
+        // Books are supposed to be sorted already, but a binary search on an unsorted
+        // file would silently miss moves.
+        entries.sort_by_key(|entry| entry.key);
+        Ok(OpeningBook { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All legal book moves for `board` with their weights.
+    pub fn moves(&self, board: &Board) -> Vec<(ChessMove, u16)> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(board, entry.mv)?, entry.weight)))
+            .collect()
+    }
+
+    /// Pick a book move for `board`, or `None` when we are out of book.
+    ///
+    /// With `randomness` 0 this always plays the move with the highest weight, with 1
+    /// moves are picked in proportion to their weights, and higher values make the
+    /// choice more and more uniform.
+    pub fn choose_move(&self, board: &Board, randomness: f64) -> Option<ChessMove> {
+        let moves: Vec<(ChessMove, u16)> =
+            self.moves(board).into_iter().filter(|&(_, weight)| weight > 0).collect();
+
+        if randomness <= 0.0 {
+            return moves.iter().max_by_key(|&&(_, weight)| weight).map(|&(mv, _)| mv);
+        }
+
+        let weights: Vec<f64> = moves
+            .iter()
+            .map(|&(_, weight)| (weight as f64).powf(1.0 / randomness))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            return moves.first().map(|&(mv, _)| mv);
+        }
+
+        let mut pick = rand::rng().random_range(0.0..total);
+        for (&(mv, _), weight) in moves.iter().zip(&weights) {
+            if pick < *weight {
+                return Some(mv);
+            }
+            pick -= weight;
+        }
+        moves.last().map(|&(mv, _)| mv)
+    }
+}
+
+/// The Polyglot hash of a position, which is what book entries are keyed by.
+/// (`Board::get_hash()` uses different random numbers.)
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+
+    for sq in *board.combined() {
+        let piece = board.piece_on(sq).unwrap();
+        let color = board.color_on(sq).unwrap();
+        let kind = 2 * piece.to_index() + usize::from(color == Color::White);
+        key ^= POLYGLOT_RANDOM[64 * kind + sq.to_index()];
+    }
+
+    for (i, color) in [Color::White, Color::Black].into_iter().enumerate() {
+        let rights = board.castle_rights(color);
+        if rights.has_kingside() {
+            key ^= POLYGLOT_RANDOM[CASTLE_OFFSET + 2 * i];
+        }
+        if rights.has_queenside() {
+            key ^= POLYGLOT_RANDOM[CASTLE_OFFSET + 2 * i + 1];
+        }
+    }
+
+    // The chess crate only records the en passant square when an enemy pawn stands
+    // next to the pawn that just moved, which is exactly Polyglot's rule.
+    if let Some(ep) = board.en_passant() {
+        key ^= POLYGLOT_RANDOM[EN_PASSANT_OFFSET + ep.get_file().to_index()];
+    }
+
+    if board.side_to_move() == Color::White {
+        key ^= POLYGLOT_RANDOM[TURN_OFFSET];
+    }
+
+    key
+}
+
+/// Turn a Polyglot move into the legal move it stands for, if any.
+fn decode_move(board: &Board, mv: u16) -> Option<ChessMove> {
+    let to = ALL_SQUARES[(mv & 0x3F) as usize];
+    let from = ALL_SQUARES[((mv >> 6) & 0x3F) as usize];
+    let promotion = match (mv >> 12) & 0x7 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+
+    // Polyglot writes castling as the king capturing its own rook.
+    let to = match (board.piece_on(from), from, to) {
+        (Some(Piece::King), Square::E1, Square::H1) => Square::G1,
+        (Some(Piece::King), Square::E1, Square::A1) => Square::C1,
+        (Some(Piece::King), Square::E8, Square::H8) => Square::G8,
+        (Some(Piece::King), Square::E8, Square::A8) => Square::C8,
+        _ => to,
+    };
+
+    MoveGen::new_legal(board).find(|legal| {
+        legal.get_source() == from && legal.get_dest() == to && legal.get_promotion() == promotion
+    })
+}
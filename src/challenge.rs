@@ -0,0 +1,147 @@
+//! Which challenges the bot accepts, and the reason Lichess shows the challenger
+//! for the ones it declines.
+use crate::config::ChallengeRules;
+use serde::Deserialize;
+
+/// A challenge as the Lichess event stream sends it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Challenge {
+    pub id: String,
+    /// Missing for challenges from anonymous players.
+    pub challenger: Option<Challenger>,
+    pub variant: Variant,
+    pub speed: String,
+    pub rated: bool,
+    #[serde(rename = "timeControl")]
+    pub time_control: TimeControl,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Challenger {
+    pub id: String,
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub rating: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Variant {
+    /// "standard", "chess960", "fromPosition", ...
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TimeControl {
+    /// Initial time and increment in seconds.
+    Clock { limit: u64, increment: u64 },
+    Correspondence {
+        #[serde(rename = "daysPerTurn")]
+        days_per_turn: u32,
+    },
+    Unlimited,
+}
+
+impl Challenge {
+    pub fn challenger_is_bot(&self) -> bool {
+        self.challenger.as_ref().is_some_and(|c| c.title.as_deref() == Some("BOT"))
+    }
+}
+
+/// Why a challenge is declined, one of the reasons Lichess knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclineReason {
+    Generic,
+    /// Too many games going on already.
+    Later,
+    TooFast,
+    TooSlow,
+    TimeControl,
+    /// We only play rated games.
+    Rated,
+    /// We only play casual games.
+    Casual,
+    /// We only play standard chess.
+    Standard,
+    Variant,
+    NoBot,
+    OnlyBot,
+}
+
+impl DeclineReason {
+    /// The key `/api/challenge/{id}/decline` takes.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeclineReason::Generic => "generic",
+            DeclineReason::Later => "later",
+            DeclineReason::TooFast => "tooFast",
+            DeclineReason::TooSlow => "tooSlow",
+            DeclineReason::TimeControl => "timeControl",
+            DeclineReason::Rated => "rated",
+            DeclineReason::Casual => "casual",
+            DeclineReason::Standard => "standard",
+            DeclineReason::Variant => "variant",
+            DeclineReason::NoBot => "noBot",
+            DeclineReason::OnlyBot => "onlyBot",
+        }
+    }
+}
+
+// Lichess speeds from fastest to slowest.
+const SPEEDS: [&str; 6] = ["ultraBullet", "bullet", "blitz", "rapid", "classical", "correspondence"];
+
+/// Accept `challenge` while `active_games` games are going on, or say why not.
+pub fn check_challenge(
+    rules: &ChallengeRules,
+    challenge: &Challenge,
+    active_games: usize,
+) -> Result<(), DeclineReason> {
+    if !rules.variants.contains(&challenge.variant.key) {
+        let only_standard = rules.variants.iter().all(|variant| variant == "standard");
+        return Err(if only_standard { DeclineReason::Standard } else { DeclineReason::Variant });
+    }
+
+    if !rules.speeds.contains(&challenge.speed) {
+        // Faster than every speed we play is "too fast", slower than every one "too slow".
+        let rank = |speed: &str| SPEEDS.iter().position(|&s| s == speed);
+        let speed = rank(&challenge.speed);
+        let allowed: Vec<usize> = rules.speeds.iter().filter_map(|s| rank(s)).collect();
+        return Err(match (speed, allowed.iter().min(), allowed.iter().max()) {
+            (Some(speed), Some(&fastest), _) if speed < fastest => DeclineReason::TooFast,
+            (Some(speed), _, Some(&slowest)) if speed > slowest => DeclineReason::TooSlow,
+            _ => DeclineReason::TimeControl,
+        });
+    }
+    if let TimeControl::Clock { limit, increment } = challenge.time_control {
+        if limit < rules.min_initial_secs || increment < rules.min_increment_secs {
+            return Err(DeclineReason::TooFast);
+        }
+        if limit > rules.max_initial_secs || increment > rules.max_increment_secs {
+            return Err(DeclineReason::TooSlow);
+        }
+    }
+
+    match challenge.rated {
+        true if !rules.rated => return Err(DeclineReason::Casual),
+        false if !rules.casual => return Err(DeclineReason::Rated),
+        _ => {}
+    }
+
+    match challenge.challenger_is_bot() {
+        true if !rules.bots => return Err(DeclineReason::NoBot),
+        false if !rules.humans => return Err(DeclineReason::OnlyBot),
+        _ => {}
+    }
+    let rating = challenge.challenger.as_ref().and_then(|c| c.rating);
+    let too_low = rules.min_rating.is_some_and(|min| rating.is_none_or(|rating| rating < min));
+    let too_high = rules.max_rating.is_some_and(|max| rating.is_some_and(|rating| rating > max));
+    if too_low || too_high {
+        return Err(DeclineReason::Generic);
+    }
+
+    // Last, so a challenge we would never play isn't told to come back later.
+    if active_games >= rules.max_games {
+        return Err(DeclineReason::Later);
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+//! Settings of the Lichess bot, read from a TOML (or JSON) file. Every setting has a
+//! default, so the file only needs the ones that differ.
+use crate::lichess::LICHESS_URL;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::path::Path;
+use std::{fs, io};
+
+/// Where the configuration is read from if neither `--config` nor this variable says.
+pub const CONFIG_ENV_VAR: &str = "TINYCHESS_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "tinychess.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    pub lichess: LichessConfig,
+    pub engine: EngineSettings,
+    pub challenges: ChallengeRules,
+    pub book: BookSettings,
+    pub syzygy: SyzygySettings,
+    pub archive: ArchiveSettings,
+    pub logging: LoggingSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LichessConfig {
+    /// Where the Lichess API is, only worth changing to test against a local server.
+    pub base_url: String,
+}
+
+impl Default for LichessConfig {
+    fn default() -> Self {
+        LichessConfig {
+            base_url: LICHESS_URL.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineSettings {
+    /// Size of the transposition table shared by all games, in megabytes.
+    pub hash_size_mb: usize,
+    /// Number of search threads, 0 means one per CPU.
+    pub threads: usize,
+    /// How much worse than equal a draw is for us, in centipawns.
+    pub contempt: i32,
+    /// Never search deeper than this.
+    pub max_depth: u32,
+    /// Time per move lost to the network, never spent thinking.
+    pub move_overhead_ms: u64,
+    /// How long to think in games without a clock.
+    pub no_clock_move_time_ms: u64,
+    /// Think on the opponent's time about the reply we expect.
+    pub ponder: bool,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        EngineSettings {
+            hash_size_mb: 64,
+            threads: 0,
+            contempt: 20,
+            max_depth: 64,
+            move_overhead_ms: 300,
+            no_clock_move_time_ms: 15_000,
+            ponder: true,
+        }
+    }
+}
+
+/// Which challenges the bot accepts, see [`crate::challenge::check_challenge`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChallengeRules {
+    /// Variant keys as Lichess sends them. We can only play "standard" (and
+    /// "fromPosition", which is standard chess from a set up position).
+    pub variants: Vec<String>,
+    /// Speeds as Lichess sends them: "ultraBullet", "bullet", "blitz", "rapid",
+    /// "classical" and "correspondence".
+    pub speeds: Vec<String>,
+    pub rated: bool,
+    pub casual: bool,
+    pub bots: bool,
+    pub humans: bool,
+    /// Limits on the initial clock time, in seconds.
+    pub min_initial_secs: u64,
+    pub max_initial_secs: u64,
+    /// Limits on the increment, in seconds.
+    pub min_increment_secs: u64,
+    pub max_increment_secs: u64,
+    /// Limits on the challenger's rating in the challenge's speed. Challenges from
+    /// players without a rating (anonymous ones) are declined if there is a minimum.
+    pub min_rating: Option<u32>,
+    pub max_rating: Option<u32>,
+    /// Games played at the same time, challenges beyond that are asked to come back later.
+    pub max_games: usize,
+}
+
+impl Default for ChallengeRules {
+    fn default() -> Self {
+        ChallengeRules {
+            variants: vec!["standard".to_string()],
+            speeds: ["bullet", "blitz", "rapid", "classical"].map(String::from).to_vec(),
+            rated: true,
+            casual: true,
+            bots: true,
+            humans: true,
+            min_initial_secs: 0,
+            max_initial_secs: 3 * 60 * 60,
+            min_increment_secs: 0,
+            max_increment_secs: 180,
+            min_rating: None,
+            max_rating: None,
+            max_games: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BookSettings {
+    /// Polyglot opening book, used while it has moves for the position. An empty
+    /// path (or null in JSON) turns the book off.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub path: Option<String>,
+    /// 0 always plays the book's favourite move, 1 picks moves in proportion to their weights.
+    pub randomness: f64,
+}
+
+impl Default for BookSettings {
+    fn default() -> Self {
+        BookSettings {
+            path: Some("book.bin".to_string()),
+            randomness: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyzygySettings {
+    /// Directories with .rtbw/.rtbz files, separated like `PATH`. None by default:
+    /// the prober hasn't been checked against real table files yet.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveSettings {
+    /// Where finished games are saved as PGN. Empty (or null in JSON) to not save them.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub dir: Option<String>,
+    /// Append every game to this file in `dir` instead of one file per game.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub database: Option<String>,
+}
+
+impl Default for ArchiveSettings {
+    fn default() -> Self {
+        ArchiveSettings {
+            dir: Some("games".to_string()),
+            database: None,
+        }
+    }
+}
+
+// TOML has no null, so an empty string is how a file turns off a path that is on
+// by default.
+fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|path| !path.is_empty()))
+}
+
+/// How much the bot prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Errors only.
+    Error,
+    /// Games starting and ending, challenges and the moves we play.
+    #[default]
+    Info,
+    /// Also the search result of every move.
+    Debug,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    pub level: LogLevel,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, io::Error),
+    /// The file isn't valid TOML/JSON or has settings we don't know.
+    Parse(String, String),
+    /// The settings are readable but make no sense, one message per problem.
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {path}: {e}"),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {path}: {e}"),
+            ConfigError::Invalid(problems) => write!(f, "invalid configuration: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl BotConfig {
+    /// Read and validate a configuration file, JSON if it ends in `.json` and TOML otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+        let config = match path.extension().is_some_and(|ext| ext == "json") {
+            true => Self::from_json(&text),
+            false => Self::from_toml(&text),
+        };
+        config.map_err(|e| match e {
+            ConfigError::Parse(_, message) => ConfigError::Parse(name, message),
+            e => e,
+        })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: BotConfig = toml::from_str(text).map_err(|e| ConfigError::Parse("TOML".to_string(), e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        let config: BotConfig =
+            serde_json::from_str(text).map_err(|e| ConfigError::Parse("JSON".to_string(), e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the settings that can be wrong even when they parse.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if !self.lichess.base_url.starts_with("http://") && !self.lichess.base_url.starts_with("https://") {
+            problems.push(format!("lichess.base_url must be an http(s) URL, not {:?}", self.lichess.base_url));
+        }
+        if self.engine.hash_size_mb == 0 {
+            problems.push("engine.hash_size_mb must be at least 1".to_string());
+        }
+        if self.engine.max_depth == 0 {
+            problems.push("engine.max_depth must be at least 1".to_string());
+        }
+        if self.engine.no_clock_move_time_ms == 0 {
+            problems.push("engine.no_clock_move_time_ms must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.book.randomness) {
+            problems.push(format!("book.randomness must be between 0 and 1, not {}", self.book.randomness));
+        }
+
+        let rules = &self.challenges;
+        for variant in &rules.variants {
+            if !matches!(variant.as_str(), "standard" | "fromPosition") {
+                problems.push(format!("challenges.variants: we can't play {variant:?}"));
+            }
+        }
+        for speed in &rules.speeds {
+            if !matches!(
+                speed.as_str(),
+                "ultraBullet" | "bullet" | "blitz" | "rapid" | "classical" | "correspondence"
+            ) {
+                problems.push(format!("challenges.speeds: unknown speed {speed:?}"));
+            }
+        }
+        if rules.min_initial_secs > rules.max_initial_secs {
+            problems.push("challenges.min_initial_secs is more than max_initial_secs".to_string());
+        }
+        if rules.min_increment_secs > rules.max_increment_secs {
+            problems.push("challenges.min_increment_secs is more than max_increment_secs".to_string());
+        }
+        if rules.min_rating.zip(rules.max_rating).is_some_and(|(min, max)| min > max) {
+            problems.push("challenges.min_rating is more than max_rating".to_string());
+        }
+        if rules.max_games == 0 {
+            problems.push("challenges.max_games must be at least 1".to_string());
+        }
+        if !rules.rated && !rules.casual {
+            problems.push("challenges: neither rated nor casual games are accepted".to_string());
+        }
+        if !rules.bots && !rules.humans {
+            problems.push("challenges: neither bots nor humans are accepted".to_string());
+        }
+        if self.archive.database.is_some() && self.archive.dir.is_none() {
+            problems.push("archive.database is set but archive.dir isn't".to_string());
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(problems)),
+        }
+    }
+}
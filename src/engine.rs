@@ -1,60 +1,264 @@
-use chess::{Board, ChessMove, MoveGen, Color, Square};
-use crate::psts::{PAWN_PST, KNIGHT_PST, BISHOP_PST, QUEEN_PST, KING_PST, ROOK_PST};
+use chess::{Board, ChessMove, MoveGen, Color};
+use crate::eval::{evaluate_board, piece_value};
+use crate::syzygy::{Tablebases, Wdl};
+use crate::time::TimeBudget;
+use crate::tt::{Bound, TranspositionTable};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
 use std::time::{Instant, Duration};
 use rayon::prelude::*;
 
-const MATE_SCORE: i32 = 100_000;
+pub const MATE_SCORE: i32 = 100_000;
+// Anything above this is a forced mate; such scores are stored in the
+// transposition table relative to the node instead of the root.
+pub const MATE_BOUND: i32 = MATE_SCORE - 1_000;
+/// Score of a position the tablebases say is won, minus the distance from the root.
+pub const TB_WIN_SCORE: i32 = MATE_BOUND - 1_000;
+// Tablebase wins and mates are both stored relative to the node.
+const TB_BOUND: i32 = TB_WIN_SCORE - 1_000;
 
-fn piece_value(piece: chess::Piece) -> i32 {
-    match piece {
-        chess::Piece::Pawn   => 100,
-        chess::Piece::Knight => 320,
-        chess::Piece::Bishop => 330,
-        chess::Piece::Rook   => 500,
-        chess::Piece::Queen  => 900,
-        chess::Piece::King   => 20_000,
+/// How long a search may run. Whichever limit is hit first ends it.
+#[derive(Debug, Clone)]
+pub struct SearchLimits {
+    pub max_depth: u32,
+    pub time: Option<TimeBudget>,
+    pub max_nodes: Option<u64>,
+    /// Set when searching on the opponent's time: `time` only starts running once
+    /// the opponent plays the move we expect, see [`PonderHit`].
+    pub ponder: Option<Arc<PonderHit>>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            max_depth: 64,
+            time: None,
+            max_nodes: None,
+            ponder: None,
+        }
     }
 }
 
-fn piece_square_value(piece: chess::Piece, square: Square, color: Color) -> i32 {
-    let idx = square.to_index() as usize;
-    let table_index = match color {
-        Color::White => idx,
-        Color::Black => 63 - idx,
-    };
+/// Shared between a pondering search and whoever sees the opponent's move. Until
+/// [`PonderHit::hit`] the search runs without a time limit.
+#[derive(Debug, Default)]
+pub struct PonderHit {
+    at: OnceLock<Instant>,
+}
 
-    match piece {
-        chess::Piece::Pawn   => PAWN_PST[table_index],
-        chess::Piece::Knight => KNIGHT_PST[table_index],
-        chess::Piece::Bishop => BISHOP_PST[table_index],
-        chess::Piece::Rook   => ROOK_PST[table_index],
-        chess::Piece::Queen  => QUEEN_PST[table_index],
-        chess::Piece::King   => KING_PST[table_index],
+impl PonderHit {
+    /// The opponent played the move we pondered on, so the search is for real now
+    /// and its time budget starts counting.
+    pub fn hit(&self) {
+        let _ = self.at.set(Instant::now());
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.at.get().is_some()
     }
 }
 
-fn evaluate_board(board: &Board) -> i32 {
-    let mut score = 0;
-    for sq in chess::ALL_SQUARES {
-        if let Some(piece) = board.piece_on(sq) {
-            let color_on_sq = board.color_on(sq).unwrap();
-            let piece_score = piece_value(piece) + piece_square_value(piece, sq, color_on_sq);
-            if color_on_sq == Color::White {
-                score += piece_score;
-            } else {
-                score -= piece_score;
+/// What we know after each completed iteration of iterative deepening.
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: u32,
+    /// Score in centipawns from the side to move's point of view.
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub best_move: ChessMove,
+    /// The line the engine expects, starting with `best_move`.
+    pub pv: Vec<ChessMove>,
+    /// Positions looked up in the endgame tablebases.
+    pub tb_hits: u64,
+}
+
+/// The game leading up to the position being searched, so the search can see
+/// repetitions and the fifty-move rule coming.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameHistory {
+    /// Hashes of the earlier positions, oldest first.
+    pub hashes: Vec<u64>,
+    /// Halfmoves since the last capture or pawn move.
+    pub halfmove_clock: u32,
+}
+
+impl GameHistory {
+    /// Record that `mv` is played on `board`.
+    pub fn push(&mut self, board: &Board, mv: ChessMove) {
+        self.hashes.push(board.get_hash());
+        self.halfmove_clock = if is_irreversible(board, mv) { 0 } else { self.halfmove_clock + 1 };
+    }
+}
+
+/// Captures and pawn moves reset the fifty-move counter, and no position before
+/// them can ever come back.
+fn is_irreversible(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_source()) == Some(chess::Piece::Pawn)
+        || board.piece_on(mv.get_dest()).is_some()
+}
+
+/// The positions from the start of the game down to the node being searched.
+/// Every root thread has its own copy.
+#[derive(Clone)]
+struct SearchStack {
+    // Hash and halfmove clock of every position, the current node last.
+    positions: Vec<(u64, u32)>,
+    // Index of the root position.
+    root: usize,
+}
+
+impl SearchStack {
+    fn new(board: &Board, history: &GameHistory) -> Self {
+        let mut positions: Vec<(u64, u32)> = history.hashes.iter().map(|&hash| (hash, 0)).collect();
+        positions.push((board.get_hash(), history.halfmove_clock));
+        SearchStack { root: positions.len() - 1, positions }
+    }
+
+    /// Distance from the root.
+    fn ply(&self) -> u32 {
+        (self.positions.len() - 1 - self.root) as u32
+    }
+
+    /// Step into the position after `mv` has been played on `board`.
+    fn push(&mut self, board: &Board, mv: ChessMove, child: &Board) {
+        let halfmove_clock = match is_irreversible(board, mv) {
+            true => 0,
+            false => self.positions.last().map_or(0, |&(_, clock)| clock + 1),
+        };
+        self.positions.push((child.get_hash(), halfmove_clock));
+    }
+
+    fn pop(&mut self) {
+        self.positions.pop();
+    }
+
+    fn halfmove_clock(&self) -> u32 {
+        self.positions.last().map_or(0, |&(_, clock)| clock)
+    }
+
+    /// Is the current position drawn by the fifty-move rule or a repetition?
+    ///
+    /// Repeating a position from after the root counts once: if repeating was the
+    /// best either side could do once, it will be again. Positions from before the
+    /// root were played in the game, where only the third time is a draw, like in
+    /// Stockfish.
+    fn is_draw(&self) -> bool {
+        let &(hash, clock) = self.positions.last().unwrap();
+        if clock >= 100 {
+            return true;
+        }
+        // Only positions since the last irreversible move, with the same side to move.
+        let current = self.positions.len() - 1;
+        let ply = current - self.root;
+        let mut earlier = 0;
+        let mut back = 4;
+        while back <= clock as usize && back <= current {
+            if self.positions[current - back].0 == hash {
+                if back < ply {
+                    return true;
+                }
+                earlier += 1;
+                if earlier == 2 {
+                    return true;
+                }
+            }
+            back += 2;
+        }
+        false
+    }
+}
+
+/// State shared by every node of one search.
+struct SearchContext<'a> {
+    tt: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    nodes: AtomicU64,
+    start_time: Instant,
+    limits: &'a SearchLimits,
+    // The game so far, ending with the root position
+    history: SearchStack,
+    root_color: Color,
+    contempt: i32,
+    tablebases: Option<&'a Tablebases>,
+    tb_hits: AtomicU64,
+    // The moves searched at the root, fewer than all legal moves when the
+    // tablebases already know which ones are best.
+    root_moves: Vec<ChessMove>,
+}
+
+impl SearchContext<'_> {
+    fn should_stop(&self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        if let Some(limit) = self.limits.max_nodes {
+            if self.nodes.load(Ordering::Relaxed) >= limit {
+                return true;
             }
         }
+        match (self.limits.time, self.clock_start()) {
+            (Some(budget), Some(start)) => start.elapsed() >= budget.hard,
+            _ => false,
+        }
+    }
+
+    /// When the time budget started counting, if it has: the start of the search, or
+    /// the ponder hit if we are pondering.
+    fn clock_start(&self) -> Option<Instant> {
+        match &self.limits.ponder {
+            Some(ponder) => ponder.at.get().copied(),
+            None => Some(self.start_time),
+        }
+    }
+
+    fn count_node(&self) {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Score of a draw for `side_to_move`. With positive contempt the engine
+    /// thinks a draw is worse than equal for itself, so it plays on.
+    fn draw_score(&self, side_to_move: Color) -> i32 {
+        if side_to_move == self.root_color {
+            -self.contempt
+        } else {
+            self.contempt
+        }
     }
-    score
 }
 
-/// Generate all legal moves, but now prioritize moves that immediately deliver mate,
-/// then captures (via MVV-LVA), then quiet moves that give check.
-fn generate_ordered_moves(board: &Board) -> Vec<ChessMove> {
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= TB_BOUND {
+        score + ply as i32
+    } else if score <= -TB_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= TB_BOUND {
+        score - ply as i32
+    } else if score <= -TB_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Generate all legal moves, but now prioritize the transposition table move,
+/// then moves that immediately deliver mate, then captures (via MVV-LVA), then
+/// quiet moves that give check.
+fn generate_ordered_moves(board: &Board, tt_move: Option<ChessMove>) -> Vec<ChessMove> {
     let mut moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
 
     moves.sort_by_key(|mv| {
+        // The best move from a previous search of this position goes first.
+        if Some(*mv) == tt_move {
+            return i32::MIN;
+        }
         // Create the new board after this move.
         let new_board = board.make_move_new(*mv);
         // If the move delivers checkmate, give it the highest priority.
@@ -84,10 +288,10 @@ fn quiesce(
     mut alpha: i32,
     beta: i32,
     color: i32,
-    start_time: Instant,
-    time_limit: Duration,
+    ctx: &SearchContext,
 ) -> i32 {
-    if start_time.elapsed() >= time_limit {
+    ctx.count_node();
+    if ctx.should_stop() {
         return color * evaluate_board(board);
     }
 
@@ -123,11 +327,11 @@ fn quiesce(
     });
 
     for mv in q_moves {
-        if start_time.elapsed() >= time_limit {
+        if ctx.should_stop() {
             return alpha;
         }
         let new_board = board.make_move_new(mv);
-        let score = -quiesce(&new_board, -beta, -alpha, -color, start_time, time_limit);
+        let score = -quiesce(&new_board, -beta, -alpha, -color, ctx);
         if score >= beta {
             return beta;
         }
@@ -138,116 +342,468 @@ fn quiesce(
     alpha
 }
 
-/// Negamax with alpha-beta pruning.
+/// Negamax with alpha-beta pruning, backed by the transposition table.
+///
+/// `pv` receives the principal variation from this node: every node builds its line
+/// from the line of its best child, which gives the same result as a triangular PV
+/// table without sharing one between the root threads.
 fn negamax(
     board: &Board,
     depth: u32,
     mut alpha: i32,
     beta: i32,
-    color: i32,
-    start_time: Instant,
-    time_limit: Duration,
+    ctx: &SearchContext,
+    stack: &mut SearchStack,
+    pv: &mut Vec<ChessMove>,
 ) -> i32 {
-    if start_time.elapsed() >= time_limit {
+    pv.clear();
+    ctx.count_node();
+    let ply = stack.ply();
+    let color = if board.side_to_move() == Color::White { 1 } else { -1 };
+    if ctx.should_stop() {
         return color * evaluate_board(board);
     }
 
     match board.status() {
         chess::BoardStatus::Ongoing => {
+            if stack.is_draw() {
+                return ctx.draw_score(board.side_to_move());
+            }
             if depth == 0 {
-                return quiesce(board, alpha, beta, color, start_time, time_limit);
+                return quiesce(board, alpha, beta, color, ctx);
             }
         }
         chess::BoardStatus::Checkmate => {
-            return -(MATE_SCORE - depth as i32);
+            return -(MATE_SCORE - ply as i32);
         }
         chess::BoardStatus::Stalemate => {
-            return 0;
+            return ctx.draw_score(board.side_to_move());
         }
     }
 
+    let hash = board.get_hash();
+    let mut tt_move = None;
+    if let Some(entry) = ctx.tt.probe(hash) {
+        tt_move = entry.best_move;
+        if entry.depth >= depth {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+    }
+
+    // Right after a capture or pawn move the tablebases know the exact result (later
+    // on the fifty-move rule may have something to say about it).
+    if let Some(wdl) = probe_wdl(board, ctx, stack) {
+        let (score, bound) = match wdl {
+            Wdl::Win => (TB_WIN_SCORE - ply as i32, Bound::Lower),
+            Wdl::Loss => (-(TB_WIN_SCORE - ply as i32), Bound::Upper),
+            _ => (ctx.draw_score(board.side_to_move()), Bound::Exact),
+        };
+        let cutoff = match bound {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        };
+        if cutoff {
+            ctx.tt.store(hash, depth, score_to_tt(score, ply), bound, None);
+            return score;
+        }
+    }
+
+    let original_alpha = alpha;
     let mut best_value = i32::MIN;
-    let mut current_alpha = alpha;
+    let mut best_move = None;
+    let mut child_pv = Vec::new();
 
-    for mv in generate_ordered_moves(board) {
-        if start_time.elapsed() >= time_limit {
+    for mv in generate_ordered_moves(board, tt_move) {
+        if ctx.should_stop() {
             break;
         }
         let new_board = board.make_move_new(mv);
-        let value = -negamax(&new_board, depth - 1, -beta, -current_alpha, -color, start_time, time_limit);
+        stack.push(board, mv, &new_board);
+        let value = -negamax(&new_board, depth - 1, -beta, -alpha, ctx, stack, &mut child_pv);
+        stack.pop();
         if value > best_value {
             best_value = value;
+            best_move = Some(mv);
         }
-        if value > current_alpha {
-            current_alpha = value;
+        if value > alpha {
+            alpha = value;
+            pv.clear();
+            pv.push(mv);
+            pv.extend_from_slice(&child_pv);
         }
-        if current_alpha >= beta {
+        if alpha >= beta {
             break;
         }
     }
 
+    // Stopped before searching a single move, the caller will throw this away anyway.
+    if best_move.is_none() {
+        return alpha;
+    }
+
+    // A search cut short by a limit is not trustworthy, so don't remember it.
+    if !ctx.should_stop() {
+        let bound = if best_value <= original_alpha {
+            Bound::Upper
+        } else if best_value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        ctx.tt.store(hash, depth, score_to_tt(best_value, ply), bound, best_move);
+    }
+
     best_value
 }
 
+fn probe_wdl(board: &Board, ctx: &SearchContext, stack: &SearchStack) -> Option<Wdl> {
+    let tablebases = ctx.tablebases?;
+    if stack.halfmove_clock() != 0 {
+        return None;
+    }
+    let wdl = tablebases.probe_wdl(board)?;
+    ctx.tb_hits.fetch_add(1, Ordering::Relaxed);
+    Some(wdl)
+}
+
 /// Parallelized search for the best move at a given depth.
 ///
 /// This version uses Rayon to evaluate each candidate move from the root in parallel.
+/// All workers share the same transposition table, so whatever one of them learns
+/// about a position is immediately available to the others.
 /// (Note: early exit on mate detection is not implemented here.)
 fn best_move_at_depth(
     board: &Board,
     depth: u32,
-    start_time: Instant,
-    time_limit: Duration,
-) -> Option<(i32, ChessMove)> {
-    let color = if board.side_to_move() == Color::White { 1 } else { -1 };
-
+    ctx: &SearchContext,
+) -> Option<(i32, Vec<ChessMove>)> {
     let alpha = i32::MIN + 1;
     let beta = i32::MAX - 1;
 
-    // Get the ordered moves at the root.
-    let moves = generate_ordered_moves(board);
+    // Get the ordered moves at the root, starting with the best move of the previous iteration.
+    let hash = board.get_hash();
+    let tt_move = ctx.tt.probe(hash).and_then(|entry| entry.best_move);
+    let mut moves = generate_ordered_moves(board, tt_move);
+    moves.retain(|mv| ctx.root_moves.contains(mv));
 
     // Evaluate each move in parallel.
-    let results: Vec<(i32, ChessMove)> = moves.par_iter()
+    let results: Vec<(i32, Vec<ChessMove>)> = moves.par_iter()
         .filter_map(|&mv| {
             // Check time in each thread.
-            if start_time.elapsed() >= time_limit {
+            if ctx.should_stop() {
                 None
             } else {
                 let new_board = board.make_move_new(mv);
-                let value = -negamax(&new_board, depth - 1, -beta, -alpha, -color, start_time, time_limit);
-                Some((value, mv))
+                let mut stack = ctx.history.clone();
+                stack.push(board, mv, &new_board);
+                let mut child_pv = Vec::new();
+                let value = -negamax(&new_board, depth - 1, -beta, -alpha, ctx, &mut stack, &mut child_pv);
+                let mut pv = vec![mv];
+                pv.extend(child_pv);
+                Some((value, pv))
             }
         })
         .collect();
 
     // Choose the move with the highest score.
-    results.into_iter().max_by_key(|(score, _)| *score)
+    let (score, mut pv) = results.into_iter().max_by_key(|(score, _)| *score)?;
+    if !ctx.should_stop() {
+        ctx.tt.store(hash, depth, score, Bound::Exact, Some(pv[0]));
+    }
+    extend_pv_from_tt(board, &mut pv, depth, ctx.tt);
+    Some((score, pv))
 }
 
-/// Iterative deepening with a 15-second time limit.
-pub fn best_move_iterative(board: &Board, max_depth: u32) -> Option<ChessMove> {
-    let start_time = Instant::now();
-    let time_limit = Duration::from_secs(15);
+/// Transposition table cutoffs leave the collected line short, so continue it with
+/// the stored best moves for as long as they are legal (and at most `depth` moves).
+fn extend_pv_from_tt(board: &Board, pv: &mut Vec<ChessMove>, depth: u32, tt: &TranspositionTable) {
+    let mut pos = *board;
+    for &mv in pv.iter() {
+        pos = pos.make_move_new(mv);
+    }
+    while pv.len() < depth as usize {
+        let Some(mv) = tt.probe(pos.get_hash()).and_then(|entry| entry.best_move) else {
+            break;
+        };
+        if !pos.legal(mv) {
+            break;
+        }
+        pv.push(mv);
+        pos = pos.make_move_new(mv);
+    }
+}
 
-    let mut best_move_overall = None;
-    let mut best_eval_overall = i32::MIN;
+/// Iterative deepening until one of the `limits` is reached or `stop` is set.
+///
+/// `on_iteration` is called after every completed depth, which is how frontends
+/// report progress. The transposition table is kept between iterations (and between
+/// calls), so each new depth starts with the best moves found by the previous one.
+/// Returns the result of the last iteration.
+fn best_move_iterative(
+    board: &Board,
+    history: &GameHistory,
+    limits: &SearchLimits,
+    engine: &Engine,
+    stop: &AtomicBool,
+    mut on_iteration: impl FnMut(&SearchInfo),
+) -> Option<SearchInfo> {
+    // In the tablebases only the moves that keep the best result are worth searching,
+    // and the fastest win among them is what makes progress.
+    let tablebases = engine.tablebases.as_ref();
+    let root_probe = tablebases.and_then(|tb| tb.probe_root(board, history.halfmove_clock));
+    let root_moves = match &root_probe {
+        Some(probe) => probe.moves.clone(),
+        None => MoveGen::new_legal(board).collect(),
+    };
 
-    for depth in 1..=max_depth {
-        if start_time.elapsed() >= time_limit {
+    let ctx = SearchContext {
+        tt: &engine.tt,
+        stop,
+        nodes: AtomicU64::new(0),
+        start_time: Instant::now(),
+        limits,
+        history: SearchStack::new(board, history),
+        root_color: board.side_to_move(),
+        contempt: engine.config.contempt,
+        tablebases,
+        tb_hits: AtomicU64::new(root_probe.is_some() as u64),
+        root_moves,
+    };
+
+    let mut best_overall: Option<SearchInfo> = None;
+    let mut soft_limit = limits.time.map(|budget| budget.soft);
+
+    for depth in 1..=limits.max_depth {
+        if ctx.should_stop() {
             break;
         }
-        if let Some((score, mv)) = best_move_at_depth(board, depth, start_time, time_limit) {
-            best_eval_overall = score;
-            best_move_overall = Some(mv);
+        if let Some((mut score, pv)) = best_move_at_depth(board, depth, &ctx) {
+            let mv = pv[0];
+            // An iteration cut short only searched some of the moves (and those maybe
+            // not fully), so stick with the last complete one.
+            if ctx.should_stop() && best_overall.is_some() {
+                break;
+            }
+
+            // The best move changed, so this position needs more thought: give it
+            // another half of the soft limit, as long as it stays under the hard limit.
+            if let (Some(budget), Some(previous)) = (limits.time, &best_overall) {
+                if previous.best_move != mv {
+                    soft_limit = soft_limit.map(|soft| (soft + budget.soft / 2).min(budget.hard));
+                }
+            }
+
+            // Unless the search found a mate, the tablebases know the result better.
+            if let Some(probe) = &root_probe {
+                if score.abs() < MATE_BOUND {
+                    score = tb_score(probe.wdl, probe.dtz, ctx.draw_score(board.side_to_move()));
+                }
+            }
+
+            let info = SearchInfo {
+                depth,
+                score,
+                nodes: ctx.nodes.load(Ordering::Relaxed),
+                elapsed: ctx.start_time.elapsed(),
+                best_move: mv,
+                pv,
+                tb_hits: ctx.tb_hits.load(Ordering::Relaxed),
+            };
+            on_iteration(&info);
+            best_overall = Some(info);
             // If we have found a mate sequence, no need to search deeper.
             if score >= MATE_SCORE - depth as i32 {
                 break;
             }
+            // Out of soft time: another iteration would likely not finish anyway.
+            let thinking_time = ctx.clock_start().map(|start| start.elapsed());
+            if soft_limit.zip(thinking_time).is_some_and(|(soft, elapsed)| elapsed >= soft) {
+                break;
+            }
         } else {
             break;
         }
     }
 
-    best_move_overall
+    // Stopped before even depth 1 finished: any legal move beats no move at all.
+    if best_overall.is_none() {
+        let color = if board.side_to_move() == Color::White { 1 } else { -1 };
+        let mut moves = generate_ordered_moves(board, None);
+        moves.retain(|mv| ctx.root_moves.contains(mv));
+        best_overall = moves.first().map(|&mv| SearchInfo {
+            depth: 0,
+            score: color * evaluate_board(&board.make_move_new(mv)),
+            nodes: ctx.nodes.load(Ordering::Relaxed),
+            elapsed: ctx.start_time.elapsed(),
+            best_move: mv,
+            pv: vec![mv],
+            tb_hits: ctx.tb_hits.load(Ordering::Relaxed),
+        });
+    }
+
+    best_overall
+}
+
+/// Score of a tablebase result at the root. Wins that the fifty-move rule spoils
+/// are draws.
+fn tb_score(wdl: Wdl, dtz: i32, draw_score: i32) -> i32 {
+    match wdl {
+        Wdl::Win => TB_WIN_SCORE - dtz,
+        Wdl::Loss => -TB_WIN_SCORE - dtz,
+        _ => draw_score,
+    }
+}
+
+/// Settings of an [`Engine`], see [`Engine::configure`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    /// Size of the transposition table in megabytes.
+    pub hash_size_mb: usize,
+    /// Number of threads searching the root moves, 0 means one per CPU.
+    pub threads: usize,
+    /// How much worse than equal a draw is for us, in centipawns. Positive values
+    /// avoid draws, negative values seek them.
+    pub contempt: i32,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            hash_size_mb: 64,
+            threads: 0,
+            contempt: 0,
+        }
+    }
+}
+
+/// The engine: search, evaluation and everything they keep between searches.
+///
+/// An `Engine` can be shared between threads (e.g. one per game), searches only need `&self`.
+pub struct Engine {
+    config: EngineConfig,
+    tt: Arc<TranspositionTable>,
+    pool: rayon::ThreadPool,
+    tablebases: Option<Tablebases>,
+}
+
+impl Engine {
+    pub fn new(config: EngineConfig) -> Self {
+        Engine {
+            tt: Arc::new(TranspositionTable::new(config.hash_size_mb)),
+            pool: build_pool(config.threads),
+            config,
+            tablebases: None,
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Apply new settings. The transposition table is only reallocated if its size changed.
+    pub fn configure(&mut self, config: EngineConfig) {
+        if config.hash_size_mb != self.config.hash_size_mb {
+            self.tt = Arc::new(TranspositionTable::new(config.hash_size_mb));
+        }
+        if config.threads != self.config.threads {
+            self.pool = build_pool(config.threads);
+        }
+        self.config = config;
+    }
+
+    /// Use these Syzygy tables from now on, or none at all.
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.tablebases = tablebases;
+    }
+
+    pub fn tablebases(&self) -> Option<&Tablebases> {
+        self.tablebases.as_ref()
+    }
+
+    /// Forget everything learned in previous searches.
+    pub fn new_game(&self) {
+        self.tt.clear();
+    }
+
+    /// Static evaluation in centipawns from White's point of view.
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        evaluate_board(board)
+    }
+
+    /// Search `board`, reached through `history`, until one of the `limits` is
+    /// reached or `stop` is set.
+    ///
+    /// `on_iteration` is called after every completed depth. Returns the result of the
+    /// deepest iteration, or `None` if there are no legal moves.
+    pub fn search(
+        &self,
+        board: &Board,
+        history: &GameHistory,
+        limits: &SearchLimits,
+        stop: &AtomicBool,
+        on_iteration: impl FnMut(&SearchInfo) + Send,
+    ) -> Option<SearchInfo> {
+        self.pool
+            .install(|| best_move_iterative(board, history, limits, self, stop, on_iteration))
+    }
+
+    /// Like [`Engine::search`], but on a background thread. The returned handle can
+    /// stop the search early, which still yields the best move found so far.
+    pub fn start_search(
+        self: &Arc<Self>,
+        board: Board,
+        history: GameHistory,
+        limits: SearchLimits,
+        on_iteration: impl FnMut(&SearchInfo) + Send + 'static,
+    ) -> SearchHandle {
+        let engine = Arc::clone(self);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            engine.search(&board, &history, &limits, &thread_stop, on_iteration)
+        });
+        SearchHandle { stop, thread }
+    }
+}
+
+/// A search running in the background, see [`Engine::start_search`].
+pub struct SearchHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Option<SearchInfo>>,
+}
+
+impl SearchHandle {
+    /// Ask the search to finish as soon as possible.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// The flag behind [`SearchHandle::stop`], for whoever needs to stop the search
+    /// without owning the handle. Setting it to `true` stops the search.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the search to finish and return its result.
+    pub fn wait(self) -> Option<SearchInfo> {
+        self.thread.join().expect("search thread panicked")
+    }
+}
+
+fn build_pool(threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("failed to create the search thread pool")
 }
@@ -0,0 +1,147 @@
+//! Test suites in EPD format (WAC, STS, ...): positions with the move(s) the engine
+//! should find (`bm`) or avoid (`am`).
+use crate::engine::{Engine, GameHistory, SearchInfo, SearchLimits};
+use crate::notation::{parse_san, MoveError};
+use chess::{Board, ChessMove};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// One line of an EPD file.
+#[derive(Debug, Clone)]
+pub struct EpdPosition {
+    pub board: Board,
+    /// The `id` operation, e.g. "WAC.001".
+    pub id: Option<String>,
+    /// Any of these solves the position.
+    pub best_moves: Vec<ChessMove>,
+    /// None of these may be played.
+    pub avoid_moves: Vec<ChessMove>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    /// Fewer than the four position fields, or a position the board can't be set up from.
+    InvalidPosition(String),
+    /// A `bm`/`am` move that doesn't parse against the position.
+    InvalidMove(MoveError),
+    /// Neither `bm` nor `am`, so there is nothing to test.
+    NoSolution,
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpdError::InvalidPosition(fen) => write!(f, "invalid position {fen:?}"),
+            EpdError::InvalidMove(e) => write!(f, "{e}"),
+            EpdError::NoSolution => write!(f, "no bm or am operation"),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl FromStr for EpdPosition {
+    type Err = EpdError;
+
+    /// Parse e.g. `r1b1k2r/... w kq - bm Qg6; id "WAC.003";`
+    fn from_str(line: &str) -> Result<Self, EpdError> {
+        let mut fields = line.split_whitespace();
+        let position: Vec<&str> = fields.by_ref().take(4).collect();
+        let fen = position.join(" ");
+        if position.len() < 4 {
+            return Err(EpdError::InvalidPosition(fen));
+        }
+        let board = Board::from_str(&fen).map_err(|_| EpdError::InvalidPosition(fen))?;
+
+        let mut epd = EpdPosition {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+        };
+        let operations = fields.collect::<Vec<_>>().join(" ");
+        for operation in split_operations(&operations) {
+            let (opcode, operands) = operation.split_once(' ').unwrap_or((&operation, ""));
+            let moves = || -> Result<Vec<ChessMove>, EpdError> {
+                operands
+                    .split_whitespace()
+                    .map(|san| parse_san(&epd.board, san).map_err(EpdError::InvalidMove))
+                    .collect()
+            };
+            match opcode {
+                "bm" => epd.best_moves = moves()?,
+                "am" => epd.avoid_moves = moves()?,
+                "id" => epd.id = Some(operands.trim().trim_matches('"').to_string()),
+                _ => {}
+            }
+        }
+
+        if epd.best_moves.is_empty() && epd.avoid_moves.is_empty() {
+            return Err(EpdError::NoSolution);
+        }
+        Ok(epd)
+    }
+}
+
+/// Split `bm Qg6; id "WAC.003";` into its operations, minding semicolons in quotes.
+fn split_operations(operations: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in operations.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ';' if !quoted => result.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    result.push(current);
+    result.into_iter().map(|op| op.trim().to_string()).filter(|op| !op.is_empty()).collect()
+}
+
+impl EpdPosition {
+    pub fn is_solution(&self, mv: ChessMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv)) && !self.avoid_moves.contains(&mv)
+    }
+}
+
+/// How the engine did on one position.
+#[derive(Debug, Clone)]
+pub struct EpdResult {
+    /// The final iteration, `None` if the search returned nothing.
+    pub info: Option<SearchInfo>,
+    pub solved: bool,
+    /// When the engine settled on a solving move for good, i.e. the end of the first
+    /// iteration from which on every best move solved the position.
+    pub time_to_solution: Option<Duration>,
+    /// Depth of that iteration.
+    pub depth_to_solution: Option<u32>,
+}
+
+/// Search `epd` with `limits` and check the move against the solution.
+pub fn run_position(engine: &Engine, epd: &EpdPosition, limits: &SearchLimits) -> EpdResult {
+    let mut found: Option<(Duration, u32)> = None;
+    let stop = AtomicBool::new(false);
+    let info = engine.search(&epd.board, &GameHistory::default(), limits, &stop, |info| {
+        if !epd.is_solution(info.best_move) {
+            found = None;
+        } else if found.is_none() {
+            found = Some((info.elapsed, info.depth));
+        }
+    });
+
+    let solved = info.as_ref().is_some_and(|info| epd.is_solution(info.best_move));
+    // The fallback move when not even depth 1 finished was never reported.
+    let found = found.filter(|_| solved);
+    EpdResult {
+        info,
+        solved,
+        time_to_solution: found.map(|(time, _)| time),
+        depth_to_solution: found.map(|(_, depth)| depth),
+    }
+}
@@ -0,0 +1,92 @@
+use chess::{Board, Color, Piece, Square};
+use crate::pawns::evaluate_pawns;
+use crate::psts::{
+    BISHOP_EG_PST, BISHOP_MG_PST, KING_EG_PST, KING_MG_PST, KNIGHT_EG_PST, KNIGHT_MG_PST,
+    PAWN_EG_PST, PAWN_MG_PST, QUEEN_EG_PST, QUEEN_MG_PST, ROOK_EG_PST, ROOK_MG_PST,
+};
+
+// Game phase at the start of the game: every minor piece counts 1, rooks 2, queens 4.
+const MAX_PHASE: i32 = 24;
+
+/// Middlegame and endgame values of a piece.
+fn piece_values(piece: Piece) -> (i32, i32) {
+    match piece {
+        Piece::Pawn   => (100, 120),
+        Piece::Knight => (320, 300),
+        Piece::Bishop => (330, 320),
+        Piece::Rook   => (500, 530),
+        Piece::Queen  => (900, 950),
+        Piece::King   => (20_000, 20_000),
+    }
+}
+
+/// Value of a piece for move ordering (MVV-LVA), where the phase doesn't matter.
+pub(crate) fn piece_value(piece: Piece) -> i32 {
+    piece_values(piece).0
+}
+
+/// Middlegame and endgame piece-square bonuses.
+fn piece_square_values(piece: Piece, square: Square, color: Color) -> (i32, i32) {
+    // Black uses the tables upside down, but not mirrored left to right: they aren't
+    // all symmetric, and a knight on f3 should be worth what one on f6 is for Black.
+    let idx = square.to_index();
+    let table_index = match color {
+        Color::White => idx,
+        Color::Black => idx ^ 56,
+    };
+
+    let (mg, eg) = match piece {
+        Piece::Pawn   => (&PAWN_MG_PST, &PAWN_EG_PST),
+        Piece::Knight => (&KNIGHT_MG_PST, &KNIGHT_EG_PST),
+        Piece::Bishop => (&BISHOP_MG_PST, &BISHOP_EG_PST),
+        Piece::Rook   => (&ROOK_MG_PST, &ROOK_EG_PST),
+        Piece::Queen  => (&QUEEN_MG_PST, &QUEEN_EG_PST),
+        Piece::King   => (&KING_MG_PST, &KING_EG_PST),
+    };
+    (mg[table_index], eg[table_index])
+}
+
+fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        Piece::Pawn | Piece::King => 0,
+    }
+}
+
+/// How much non-pawn material is left, from `MAX_PHASE` (all of it, middlegame)
+/// down to 0 (bare kings and pawns, endgame). Promotions can't push it past `MAX_PHASE`.
+pub fn game_phase(board: &Board) -> i32 {
+    let phase: i32 = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|&piece| board.pieces(piece).popcnt() as i32 * phase_weight(piece))
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+/// Static evaluation in centipawns from White's point of view.
+///
+/// Material, piece-square tables and pawn structure. Middlegame and endgame scores
+/// are computed separately and blended by the game phase, so e.g. the king hides in
+/// the middlegame and centralizes in the endgame.
+pub fn evaluate_board(board: &Board) -> i32 {
+    let mut mg = 0;
+    let mut eg = 0;
+    for sq in *board.combined() {
+        let piece = board.piece_on(sq).unwrap();
+        let color_on_sq = board.color_on(sq).unwrap();
+        let (mg_value, eg_value) = piece_values(piece);
+        let (mg_pst, eg_pst) = piece_square_values(piece, sq, color_on_sq);
+        let sign = if color_on_sq == Color::White { 1 } else { -1 };
+        mg += sign * (mg_value + mg_pst);
+        eg += sign * (eg_value + eg_pst);
+    }
+
+    let (pawns_mg, pawns_eg) = evaluate_pawns(board);
+    mg += pawns_mg;
+    eg += pawns_eg;
+
+    let phase = game_phase(board);
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
@@ -0,0 +1,27 @@
+//! tinychess: a very tiny chess engine.
+//!
+//! [`Engine`] is the entry point: it owns the search state and exposes search,
+//! evaluation and configuration. The move string helpers shared by the Lichess bot
+//! and the UCI frontend live in [`notation`].
+pub mod archive;
+pub mod bench;
+pub mod book;
+pub mod challenge;
+pub mod config;
+pub mod engine;
+pub mod epd;
+pub mod eval;
+pub mod lichess;
+pub mod notation;
+mod pawns;
+pub mod perft;
+pub mod pgn;
+mod polyglot_random;
+mod psts;
+pub mod selfplay;
+pub mod syzygy;
+pub mod time;
+mod tt;
+
+pub use engine::{Engine, EngineConfig, GameHistory, PonderHit, SearchHandle, SearchInfo, SearchLimits};
+pub use time::{Clock, TimeBudget, TimeManager};
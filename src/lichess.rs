@@ -0,0 +1,196 @@
+//! The few Lichess API calls the bot makes, going through one client that keeps to
+//! Lichess's rules: one request at a time, and a full minute of silence after a 429.
+use crate::challenge::DeclineReason;
+use futures_util::TryStreamExt;
+use reqwest::{Method, Response, StatusCode};
+use serde::Deserialize;
+use std::{fmt, io, pin::Pin, time::Duration};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tokio_util::io::StreamReader;
+
+pub const LICHESS_URL: &str = "https://lichess.org";
+
+// https://lichess.org/page/api-tips asks to wait a minute after being rate limited.
+const RATE_LIMIT_PAUSE: Duration = Duration::from_secs(60);
+// Tries of a request before giving up, and the pause before the second one.
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The lines of a streaming endpoint, keep-alive (empty) lines included.
+pub type LineStream = Pin<Box<dyn Stream<Item = io::Result<String>> + Send>>;
+
+/// The account a token belongs to, from `/api/account`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum LichessError {
+    /// Lichess couldn't be reached, or the connection broke.
+    Network(reqwest::Error),
+    /// Lichess answered with an error. `body` usually says why in a bit of JSON.
+    Status { status: StatusCode, body: String },
+    /// Lichess answered, but not with what we expected.
+    Decode(String),
+}
+
+impl LichessError {
+    /// Whether trying again later is pointless, e.g. for a bad token or a game that
+    /// doesn't exist. Being rate limited isn't.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            LichessError::Status { status, .. } => {
+                status.is_client_error() && *status != StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for LichessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LichessError::Network(e) => write!(f, "{e}"),
+            LichessError::Status { status, body } if body.is_empty() => write!(f, "Lichess answered {status}"),
+            LichessError::Status { status, body } => write!(f, "Lichess answered {status}: {body}"),
+            LichessError::Decode(e) => write!(f, "unexpected answer from Lichess: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LichessError {}
+
+impl From<reqwest::Error> for LichessError {
+    fn from(e: reqwest::Error) -> Self {
+        LichessError::Network(e)
+    }
+}
+
+pub struct LichessClient {
+    http: reqwest::Client,
+    token: String,
+    base_url: String,
+    rate_limit_pause: Duration,
+    // Requests wait for this lock, so only one is out at a time. It holds when the
+    // next one may go, which is a minute away after a 429.
+    next_request: Mutex<Instant>,
+}
+
+impl LichessClient {
+    /// A client for the API at `base_url`, normally [`LICHESS_URL`].
+    pub fn new(base_url: &str, token: impl Into<String>) -> Result<Self, LichessError> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("tinychess/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(LichessClient {
+            http,
+            token: token.into(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            rate_limit_pause: RATE_LIMIT_PAUSE,
+            next_request: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Pause for `pause` instead of a minute after a 429, for tests against a local server.
+    pub fn with_rate_limit_pause(mut self, pause: Duration) -> Self {
+        self.rate_limit_pause = pause;
+        self
+    }
+
+    pub async fn account(&self) -> Result<Account, LichessError> {
+        let resp = self.send(Method::GET, "/api/account", &[]).await?;
+        let text = resp.text().await?;
+        serde_json::from_str(&text).map_err(|e| LichessError::Decode(e.to_string()))
+    }
+
+    /// The lines of `/api/stream/event`: challenges, and games starting and ending.
+    pub async fn stream_events(&self) -> Result<LineStream, LichessError> {
+        self.stream("/api/stream/event").await
+    }
+
+    /// The lines of a game's stream, starting with the full game and then a new state
+    /// after every move.
+    pub async fn stream_game(&self, game_id: &str) -> Result<LineStream, LichessError> {
+        self.stream(&format!("/api/bot/game/stream/{game_id}")).await
+    }
+
+    /// Play `uci` in a game. Lichess says no with a 400 when it isn't legal or not our turn.
+    pub async fn make_move(&self, game_id: &str, uci: &str) -> Result<(), LichessError> {
+        self.send(Method::POST, &format!("/api/bot/game/{game_id}/move/{uci}"), &[]).await?;
+        Ok(())
+    }
+
+    pub async fn accept_challenge(&self, challenge_id: &str) -> Result<(), LichessError> {
+        self.send(Method::POST, &format!("/api/challenge/{challenge_id}/accept"), &[]).await?;
+        Ok(())
+    }
+
+    pub async fn decline_challenge(&self, challenge_id: &str, reason: DeclineReason) -> Result<(), LichessError> {
+        let path = format!("/api/challenge/{challenge_id}/decline");
+        self.send(Method::POST, &path, &[("reason", reason.as_str())]).await?;
+        Ok(())
+    }
+
+    // Only opening the stream is a request, reading it doesn't hold up the others.
+    async fn stream(&self, path: &str) -> Result<LineStream, LichessError> {
+        let resp = self.send(Method::GET, path, &[]).await?;
+        let bytes = resp.bytes_stream().map_err(io::Error::other);
+        let lines = FramedRead::new(StreamReader::new(bytes), LinesCodec::new());
+        Ok(Box::pin(lines.map(|line| line.map_err(io::Error::other))))
+    }
+
+    /// Send a request once every earlier one is done. A 429 means Lichess didn't act on
+    /// it, so any request is tried again after the pause; network and server errors
+    /// only retry GETs, since a POST may have gone through.
+    async fn send(&self, method: Method, path: &str, form: &[(&str, &str)]) -> Result<Response, LichessError> {
+        let retries_errors = method == Method::GET;
+        let mut attempt = 1;
+        loop {
+            let result = {
+                let mut next_request = self.next_request.lock().await;
+                sleep_until(*next_request).await;
+                let mut request = self
+                    .http
+                    .request(method.clone(), format!("{}{path}", self.base_url))
+                    .bearer_auth(&self.token);
+                if !form.is_empty() {
+                    request = request.form(form);
+                }
+                let result = request.send().await;
+                if result.as_ref().is_ok_and(|resp| resp.status() == StatusCode::TOO_MANY_REQUESTS) {
+                    *next_request = Instant::now() + self.rate_limit_pause;
+                }
+                result
+            };
+
+            let retry = match &result {
+                Ok(resp) if resp.status().is_success() => false,
+                Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => true,
+                Ok(resp) => retries_errors && resp.status().is_server_error(),
+                Err(_) => retries_errors,
+            };
+            if retry && attempt < MAX_ATTEMPTS {
+                // The rate limit pause is already waited out by the next attempt
+                if !result.as_ref().is_ok_and(|resp| resp.status() == StatusCode::TOO_MANY_REQUESTS) {
+                    sleep(RETRY_DELAY * attempt).await;
+                }
+                attempt += 1;
+                continue;
+            }
+
+            let resp = result?;
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(resp);
+            }
+            let body = resp.text().await.unwrap_or_default();
+            return Err(LichessError::Status { status, body });
+        }
+    }
+}
@@ -1,24 +1,34 @@
 //interfacing best moves to lichess bot, ignore and create your own GUI if you dont want to use lichess
-mod engine;
-mod psts;
-use std::time::Duration;
-use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
-use engine::best_move_iterative;
-use futures_util::TryStreamExt;
-use reqwest::{header::USER_AGENT, Client};
+use std::time::{Duration, Instant};
+use chess::{Board, BoardStatus, ChessMove, Color};
+use tinychess::book::OpeningBook;
+use tinychess::challenge::{check_challenge, Challenge};
+use tinychess::syzygy::Tablebases;
+use tinychess::notation::{apply_uci_moves, format_move_as_uci, format_moves_as_uci, parse_uci_move, MoveError};
+use tinychess::archive::{ArchiveMode, GameArchive};
+use tinychess::pgn::{eval_comment, format_date, move_time_comment, PgnGame};
+use tinychess::lichess::{LichessClient, LichessError};
 use serde::Deserialize;
-use std::{env, error::Error, str::FromStr, sync::Arc};
-use tokio::{spawn, sync::Mutex};
-use tokio_stream::{Stream, StreamExt};
-use tokio_util::{
-    codec::{FramedRead, LinesCodec, LinesCodecError},
-    io::StreamReader,
+use std::{env, error::Error, fmt, path::Path, str::FromStr, sync::{atomic::{AtomicBool, Ordering}, Arc, OnceLock}};
+use tinychess::config::{
+    BotConfig, ConfigError, EngineSettings, LogLevel, CONFIG_ENV_VAR, DEFAULT_CONFIG_PATH,
 };
+use tinychess::{
+    Clock, Engine, EngineConfig, GameHistory, PonderHit, SearchInfo, SearchLimits, TimeBudget, TimeManager,
+};
+use rand::Rng;
+use tokio::{spawn, sync::{mpsc, Mutex}, task::{spawn_blocking, JoinHandle}};
+use tokio_stream::StreamExt;
 
 // Update these structs to parse color info from Lichess
 #[derive(Debug, Deserialize)]
 struct Player {
+    // Missing when the player is the Lichess AI
+    #[serde(default)]
     id: String,
+    name: Option<String>,
+    rating: Option<u32>,
+    title: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,11 +42,6 @@ enum IncomingEvent {
     Other,
 }
 
-#[derive(Debug, Deserialize)]
-struct Challenge {
-    id: String,
-}
-
 #[derive(Debug, Deserialize)]
 struct Game {
     id: String,
@@ -47,50 +52,325 @@ struct Game {
 #[serde(tag = "type")]
 enum GameEvent {
     #[serde(rename = "gameFull")]
-    GameFull {
-        id: String,
-        white: Player,
-        black: Player,
-        state: GameState,
-    },
+    GameFull(Box<GameFull>),
     #[serde(rename = "gameState")]
     GameState(GameState),
     #[serde(other)]
     Other,
 }
 
+#[derive(Debug, Deserialize)]
+struct GameFull {
+    id: String,
+    white: Player,
+    black: Player,
+    state: GameState,
+    // The rest is only used for the PGN of the game
+    rated: Option<bool>,
+    speed: Option<String>,
+    clock: Option<GameClock>,
+    #[serde(rename = "createdAt")]
+    created_at: Option<u64>,
+    // "startpos", or the FEN of a game from a position
+    #[serde(rename = "initialFen")]
+    initial_fen: Option<String>,
+}
+
+// Initial time and increment in milliseconds
+#[derive(Debug, Deserialize)]
+struct GameClock {
+    initial: u64,
+    increment: u64,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 struct GameState {
     moves: String,
     status: Option<String>,
+    // "white" or "black" once the game is decided
+    winner: Option<String>,
+    // Clocks in milliseconds, missing for games without a clock
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+}
+
+impl GameState {
+    /// Clock of the given side, if this game has one.
+    fn clock(&self, color: Color) -> Option<Clock> {
+        let (remaining, increment) = match color {
+            Color::White => (self.wtime?, self.winc.unwrap_or(0)),
+            Color::Black => (self.btime?, self.binc.unwrap_or(0)),
+        };
+        Some(Clock {
+            remaining: Duration::from_millis(remaining),
+            increment: Duration::from_millis(increment),
+            moves_to_go: None,
+        })
+    }
+
+    /// Full moves played so far.
+    fn moves_played(&self) -> u32 {
+        self.moves.split_whitespace().count() as u32 / 2
+    }
+
+    /// Lichess's status if the game is over.
+    fn final_status(&self) -> Option<&str> {
+        self.status.as_deref().filter(|status| !matches!(*status, "created" | "started"))
+    }
+
+    /// The PGN result: "1-0", "0-1", "1/2-1/2", or "*" while the game goes on.
+    fn result(&self) -> &'static str {
+        match (self.status.as_deref(), self.winner.as_deref()) {
+            (_, Some("white")) => "1-0",
+            (_, Some("black")) => "0-1",
+            (None | Some("created" | "started" | "aborted" | "noStart" | "unknownFinish"), _) => "*",
+            _ => "1/2-1/2",
+        }
+    }
+}
+
+impl GameFull {
+    /// The position the game started from.
+    fn start(&self) -> Result<Board, String> {
+        match self.initial_fen.as_deref() {
+            None | Some("startpos") => Ok(Board::default()),
+            Some(fen) => Board::from_str(fen).map_err(|_| format!("invalid initial FEN {fen:?} in game {}", self.id)),
+        }
+    }
+
+    /// A PGN of the game from `start` with the tags Lichess exports, but no moves yet.
+    /// The site is the game's page on `base_url`.
+    fn pgn(&self, base_url: &str, start: Board) -> PgnGame {
+        let mut pgn = PgnGame::new(start);
+        let rated = if self.rated.unwrap_or(false) { "Rated" } else { "Casual" };
+        let speed = self.speed.as_deref().unwrap_or("standard");
+        pgn.set_tag("Event", format!("{rated} {speed} game"));
+        pgn.set_tag("Site", format!("{base_url}/{}", self.id));
+        if let Some(created_at) = self.created_at {
+            pgn.set_tag("Date", format_date(created_at));
+        }
+        pgn.set_tag("Round", "-");
+        for (color, player) in [("White", &self.white), ("Black", &self.black)] {
+            pgn.set_tag(color, player.name.as_deref().unwrap_or(&player.id));
+        }
+        pgn.set_tag("Result", self.state.result());
+        for (color, player) in [("White", &self.white), ("Black", &self.black)] {
+            if let Some(rating) = player.rating {
+                pgn.set_tag(&format!("{color}Elo"), rating.to_string());
+            }
+            if let Some(title) = &player.title {
+                pgn.set_tag(&format!("{color}Title"), title.as_str());
+            }
+        }
+        let time_control = match &self.clock {
+            Some(clock) => format!("{}+{}", clock.initial / 1000, clock.increment / 1000),
+            None => "-".to_string(),
+        };
+        pgn.set_tag("TimeControl", time_control);
+        // Lichess's FEN rather than the board's, which doesn't know the move number
+        if let Some(fen) = self.initial_fen.as_deref().filter(|&fen| fen != "startpos") {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", fen);
+        }
+        pgn
+    }
 }
 
 // Track game state locally, including which color we play
 struct GameTracker {
+    // Where the game started, and the UCI moves played since, as Lichess sent them
+    start: Board,
+    moves: Vec<String>,
     board: Board,
+    // Every position before `board`, so the engine sees repetitions coming
+    history: GameHistory,
     my_color: Color,
     game_id: String,
+    // Our clock as of the last game state
+    clock: Option<Clock>,
+    moves_played: u32,
+    // The game so far, with our evals as comments on our moves
+    pgn: PgnGame,
+    // What Lichess says ended the game ("mate", "resign", ...), once it is over
+    final_status: Option<String>,
 }
 
-// Replace with your actual bot's username on Lichess:
-const MY_BOT_USERNAME: &str = "insert-your-bot-username-here";
+impl GameTracker {
+    /// Bring the board up to date with the UCI `moves` Lichess sent. Normally the
+    /// moves we know are a prefix of them and only the new ones are played; after a
+    /// takeback, or a new move we can't play, the whole game is replayed instead.
+    /// Nothing changes if even that fails.
+    fn sync_moves(&mut self, moves: &str) -> Result<(), MoveError> {
+        let moves: Vec<&str> = moves.split_whitespace().collect();
+        let known = self.moves.len();
+        if moves.len() >= known && self.moves.iter().zip(&moves).all(|(ours, theirs)| ours == theirs) {
+            let (mut board, mut history) = (self.board, self.history.clone());
+            match apply_uci_moves(&mut board, &mut history, &moves[known..].join(" ")) {
+                Ok(()) => {
+                    self.board = board;
+                    self.history = history;
+                    self.moves.extend(moves[known..].iter().map(|mv| mv.to_string()));
+                    return Ok(());
+                }
+                Err(e) => eprintln!(
+                    "Game {}: {e} after {known} moves, our board is out of sync with Lichess, replaying the game",
+                    self.game_id
+                ),
+            }
+        } else {
+            eprintln!(
+                "Game {}: the {} moves from Lichess don't continue our {known}, out of sync (a takeback?), replaying the game",
+                self.game_id,
+                moves.len()
+            );
+        }
+
+        let (mut board, mut history) = (self.start, GameHistory::default());
+        apply_uci_moves(&mut board, &mut history, &moves.join(" "))?;
+        self.board = board;
+        self.history = history;
+        self.moves = moves.iter().map(|mv| mv.to_string()).collect();
+        Ok(())
+    }
+}
+
+// Everything a game needs, shared by all of them.
+struct Bot {
+    lichess: Arc<LichessClient>,
+    // Our Lichess id, always lowercase
+    id: String,
+    config: BotConfig,
+    // One engine for the whole bot, it is safe to share between games.
+    engine: Arc<Engine>,
+    book: Option<OpeningBook>,
+    archive: Option<GameArchive>,
+    // For concurrency safety if you want to track multiple games
+    active_games: Mutex<Vec<String>>,
+}
+
+static LOG_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+
+fn log_level() -> LogLevel {
+    LOG_LEVEL.get().copied().unwrap_or_default()
+}
+
+// Games, challenges and moves; errors always go to stderr with eprintln.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if log_level() >= LogLevel::Info {
+            println!($($arg)*);
+        }
+    };
+}
+
+// The search behind every move.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if log_level() >= LogLevel::Debug {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// The configuration file from `--config <path>`, the TINYCHESS_CONFIG variable, or
+/// tinychess.toml if it exists. Without any, the defaults are used.
+fn load_config() -> Result<BotConfig, ConfigError> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let from_args = args.iter().position(|arg| arg == "--config").and_then(|i| args.get(i + 1)).cloned();
+    match from_args.or_else(|| env::var(CONFIG_ENV_VAR).ok()) {
+        Some(path) => BotConfig::load(path),
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => BotConfig::load(DEFAULT_CONFIG_PATH),
+        None => Ok(BotConfig::default()),
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let _ = LOG_LEVEL.set(config.logging.level);
+
     let token = env::var("LICHESS_BOT_TOKEN")
         .expect("Please set LICHESS_BOT_TOKEN in your environment!");
 
-    let client = Client::builder().user_agent(USER_AGENT).build()?;
+    let lichess = Arc::new(LichessClient::new(&config.lichess.base_url, token)?);
 
-    let event_stream_url = "https://lichess.org/api/stream/event";
-    let mut event_stream = stream_endpoint(&client, &token, event_stream_url).await?;
+    let account = match lichess.account().await {
+        Ok(account) => account,
+        Err(e) => {
+            eprintln!("Could not look up the account of LICHESS_BOT_TOKEN: {e}");
+            std::process::exit(1);
+        }
+    };
+    if account.title.as_deref() != Some("BOT") {
+        eprintln!(
+            "{} is not a bot account, upgrade it with https://lichess.org/api#tag/Bot/operation/botAccountUpgrade first",
+            account.username
+        );
+        std::process::exit(1);
+    }
+    info!("Logged in as {}", account.username);
+
+    let mut engine = Engine::new(EngineConfig {
+        hash_size_mb: config.engine.hash_size_mb,
+        threads: config.engine.threads,
+        contempt: config.engine.contempt,
+    });
+    if let Some(path) = &config.syzygy.path {
+        match Tablebases::open(path) {
+            Ok(tablebases) => {
+                info!(
+                    "Loaded {} tablebases from {path} (up to {} pieces)",
+                    tablebases.len(),
+                    tablebases.max_pieces()
+                );
+                engine.set_tablebases(Some(tablebases));
+            }
+            Err(e) => info!("No tablebases loaded ({path}: {e})"),
+        }
+    }
+
+    let archive = config.archive.dir.as_ref().map(|dir| {
+        let mode = match &config.archive.database {
+            Some(file) => ArchiveMode::Database(file.clone()),
+            None => ArchiveMode::FilePerGame,
+        };
+        GameArchive::new(dir, mode)
+    });
+
+    let book = config.book.path.as_ref().and_then(|path| match OpeningBook::open(path) {
+        Ok(book) => {
+            info!("Loaded opening book {path} ({} entries)", book.len());
+            Some(book)
+        }
+        Err(e) => {
+            info!("No opening book loaded ({path}: {e}), searching from the first move");
+            None
+        }
+    });
 
-    println!("Bot is now listening for events...");
+    let bot = Arc::new(Bot {
+        lichess: Arc::clone(&lichess),
+        id: account.id.to_lowercase(),
+        config,
+        engine: Arc::new(engine),
+        book,
+        archive,
+        active_games: Mutex::new(Vec::new()),
+    });
 
-    // For concurrency safety if you want to track multiple games
-    let active_games = Arc::new(Mutex::new(Vec::new()));
+    let mut events = spawn_stream(Arc::clone(&lichess), StreamKind::Events);
+
+    info!("Bot is now listening for events...");
 
-    while let Some(Ok(line_str)) = event_stream.next().await {
+    while let Some(line_str) = events.recv().await {
         // Ignore keep-alive lines or empty lines
         if line_str.trim().is_empty() {
             continue;
@@ -98,22 +378,47 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
         match serde_json::from_str::<IncomingEvent>(&line_str) {
             Ok(IncomingEvent::Challenge { challenge }) => {
-                println!("Challenge received: {:?}... Accepting...", challenge.id);
-                accept_challenge(&client, &token, &challenge.id).await?;
+                // Challenges we sent show up here too
+                if challenge.challenger.as_ref().is_some_and(|c| c.id.eq_ignore_ascii_case(&bot.id)) {
+                    continue;
+                }
+                let active_games = bot.active_games.lock().await.len();
+                match check_challenge(&bot.config.challenges, &challenge, active_games) {
+                    Ok(()) => {
+                        info!("Challenge received: {:?}... Accepting...", challenge.id);
+                        if let Err(e) = lichess.accept_challenge(&challenge.id).await {
+                            eprintln!("Could not accept challenge {}: {e}", challenge.id);
+                        }
+                    }
+                    Err(reason) => {
+                        info!("Challenge received: {:?}... Declining ({})", challenge.id, reason.as_str());
+                        if let Err(e) = lichess.decline_challenge(&challenge.id, reason).await {
+                            eprintln!("Could not decline challenge {}: {e}", challenge.id);
+                        }
+                    }
+                }
             }
             Ok(IncomingEvent::GameStart { game }) => {
-                println!("Game started: {:?}", game.id);
+                // After a reconnect Lichess sends gameStart again for the games going on,
+                // the ones we are still playing keep their handler.
+                {
+                    let mut ag = bot.active_games.lock().await;
+                    if ag.contains(&game.id) {
+                        continue;
+                    }
+                    ag.push(game.id.clone());
+                }
+                info!("Game started: {:?}", game.id);
                 let game_id = game.id.clone();
-                let client_clone = client.clone();
-                let token_clone = token.clone();
-                let active_games_ref = Arc::clone(&active_games);
+                let bot_ref = Arc::clone(&bot);
 
                 spawn(async move {
-                    if let Err(err) =
-                        handle_game(&client_clone, &token_clone, &game_id, active_games_ref).await
-                    {
+                    if let Err(err) = handle_game(&game_id, Arc::clone(&bot_ref)).await {
                         eprintln!("Error in game {game_id}: {err}");
                     }
+                    // Remove this game from "active games"
+                    let mut ag = bot_ref.active_games.lock().await;
+                    ag.retain(|id| *id != game_id);
                 });
             }
             Ok(IncomingEvent::Other) => {}
@@ -121,215 +426,440 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    Ok(())
+    Err("Lichess refused the event stream".into())
 }
 
-async fn handle_game(
-    client: &Client,
-    token: &str,
-    game_id: &str,
-    active_games_ref: Arc<Mutex<Vec<String>>>,
-) -> Result<(), Box<dyn Error>> {
-    // Add this game to "active games"
-    {
-        let mut ag = active_games_ref.lock().await;
-        ag.push(game_id.to_string());
-    }
-
-    let url = format!("https://lichess.org/api/bot/game/stream/{game_id}");
-    let mut stream = stream_endpoint(client, token, &url).await?;
+async fn handle_game(game_id: &str, bot: Arc<Bot>) -> Result<(), Box<dyn Error>> {
+    // Reconnects by itself, and Lichess starts every connection with gameFull
+    let mut stream = spawn_stream(Arc::clone(&bot.lichess), StreamKind::Game(game_id.to_string()));
 
     // We'll maintain our local tracker, which includes board + color
     let mut tracker: Option<GameTracker> = None;
 
-    while let Some(Ok(line_str)) = stream.next().await {
-        if line_str.trim().is_empty() {
-            continue;
-        }
+    // Our search, if we are thinking. It runs in the background so we notice
+    // when the game ends (or the opponent resigns) while we think.
+    let mut thinking: Option<PendingMove> = None;
 
-        match serde_json::from_str::<GameEvent>(&line_str) {
-            // This event gives us the full game state, including which side is which
-            Ok(GameEvent::GameFull { id, white, black, state }) => {
-                println!("Received GameFull for game {id}");
-
-                // Figure out if we are White or Black
-                let my_color = if white.id == MY_BOT_USERNAME {
-                    Color::White
-                } else {
-                    Color::Black
-                };
-
-                // Create a default board, then apply all moves so far
-                let mut board = Board::default();
-                apply_uci_moves(&mut board, &state.moves);
-
-                // Store into our local tracker
-                tracker = Some(GameTracker {
-                    board,
-                    my_color,
-                    game_id: id,
-                });
+    loop {
+        tokio::select! {
+            line = stream.recv() => {
+                let Some(line_str) = line else { break };
+                if line_str.trim().is_empty() {
+                    continue;
+                }
 
-                // If it's our turn, try a move
-                if let Some(t) = &mut tracker {
-                    if t.board.side_to_move() == t.my_color
-                        && t.board.status() == BoardStatus::Ongoing
-                    {
-                        try_play_move(client, token, t).await?;
+                match serde_json::from_str::<GameEvent>(&line_str) {
+                    // This event gives us the full game state, including which side is which
+                    Ok(GameEvent::GameFull(game)) => {
+                        info!("Received GameFull for game {}: {} vs {}", game.id, game.white.id, game.black.id);
+                        let start = game.start()?;
+                        let mut pgn = game.pgn(&bot.config.lichess.base_url, start);
+                        let GameFull { id, white, black, state, .. } = *game;
+
+                        // Figure out if we are White or Black. Ids are lowercase, but
+                        // don't count on it.
+                        let my_color = if white.id.eq_ignore_ascii_case(&bot.id) {
+                            Color::White
+                        } else if black.id.eq_ignore_ascii_case(&bot.id) {
+                            Color::Black
+                        } else {
+                            return Err(format!("{} is not playing in game {id}", bot.id).into());
+                        };
+
+                        // A reconnect, keep the comments on our moves
+                        if let Some(old) = tracker.take() {
+                            info!("Resuming game {id} after a reconnect");
+                            pgn.moves = old.pgn.moves;
+                        }
+                        sync_pgn(&mut pgn, &state.moves);
+
+                        // Store into our local tracker, then play all moves so far
+                        let t = tracker.insert(GameTracker {
+                            start,
+                            moves: Vec::new(),
+                            board: start,
+                            history: GameHistory::default(),
+                            my_color,
+                            game_id: id,
+                            clock: state.clock(my_color),
+                            moves_played: state.moves_played(),
+                            pgn,
+                            final_status: state.final_status().map(str::to_string),
+                        });
+                        // If even the full replay fails there is no following this game
+                        t.sync_moves(&state.moves)?;
+
+                        // It ended while we were disconnected
+                        if let Some(status) = state.final_status() {
+                            info!("Game {game_id} ended with status {status}");
+                            break;
+                        }
+                    }
+
+                    // This event updates us with new moves in the game
+                    Ok(GameEvent::GameState(state)) => {
+                        if let Some(t) = &mut tracker {
+                            t.sync_moves(&state.moves)?;
+                            t.clock = state.clock(t.my_color);
+                            t.moves_played = state.moves_played();
+                            sync_pgn(&mut t.pgn, &state.moves);
+                            t.pgn.set_tag("Result", state.result());
+
+                            // Check if game ended
+                            if let Some(status) = state.final_status() {
+                                info!("Game {} ended with status {}", t.game_id, status);
+                                t.final_status = Some(status.to_string());
+                                break;
+                            }
+                        }
                     }
+                    Ok(GameEvent::Other) => {}
+                    Err(e) => eprintln!("Error parsing game event: {e}. Raw line = {line_str}"),
                 }
-            }
 
-            // This event updates us with new moves in the game
-            Ok(GameEvent::GameState(state)) => {
                 if let Some(t) = &mut tracker {
-                    // Rebuild board from scratch
-                    let mut new_board = Board::default();
-                    apply_uci_moves(&mut new_board, &state.moves);
-                    t.board = new_board;
-
-                    // Check if game ended
-                    if let Some(status) = &state.status {
-                        if status != "started" {
-                            println!("Game {} ended with status {}", t.game_id, status);
-                            break;
+                    // The position changed under the search (e.g. a takeback, or not the
+                    // reply we pondered on), it is useless now
+                    if thinking.as_ref().is_some_and(|p| !p.fits(&t.board)) {
+                        let pending = thinking.take().unwrap();
+                        if pending.is_pondering() {
+                            debug!("Game {}: ponder miss", t.game_id);
                         }
+                        pending.stop();
+                    }
+                    // The reply we pondered on, the search goes on as the real one
+                    if let Some(pending) = thinking.as_mut().filter(|p| p.is_pondering() && p.board == t.board) {
+                        debug!("Game {}: ponder hit", t.game_id);
+                        pending.ponder_hit();
                     }
 
-                    // If it's our turn, try a move
-                    if t.board.side_to_move() == t.my_color
-                        && t.board.status() == BoardStatus::Ongoing
-                    {
-                        try_play_move(client, token, t).await?;
+                    // If it's our turn, play from the book or start thinking
+                    if thinking.is_none() && is_our_turn(t) {
+                        let book_move = bot.book.as_ref().and_then(|b| b.choose_move(&t.board, bot.config.book.randomness));
+                        match book_move {
+                            Some(mv) => {
+                                info!("Game {}: book move {}", t.game_id, format_move_as_uci(mv));
+                                play_move(&bot.lichess, t, mv, Some("book".to_string())).await?;
+                            }
+                            None => thinking = Some(start_thinking(&bot.engine, &bot.config.engine, t)),
+                        }
+                    }
+                }
+            }
+
+            // A ponder search that finished early waits for the opponent's move
+            result = async { (&mut thinking.as_mut().unwrap().result).await }, if thinking.as_ref().is_some_and(|p| !p.is_pondering()) => {
+                let pending = thinking.take().unwrap();
+                if let (Some(t), Ok(Some(info))) = (&mut tracker, result) {
+                    if t.board == pending.board {
+                        debug!(
+                            "Game {}: depth {} score {} pv {}",
+                            t.game_id,
+                            info.depth,
+                            info.score,
+                            format_moves_as_uci(&info.pv)
+                        );
+                        let comment = format!(
+                            "{} {}",
+                            eval_comment(&t.board, &info),
+                            move_time_comment(pending.started.elapsed())
+                        );
+                        let played = play_move(&bot.lichess, t, info.best_move, Some(comment)).await?;
+
+                        // Think about our next move on the opponent's time, assuming they
+                        // reply the way the search expects
+                        let expected = info.pv.get(1).copied().filter(|&mv| t.board.legal(mv));
+                        if let (true, true, Some(expected)) = (played, bot.config.engine.ponder, expected) {
+                            let spent = pending.started.elapsed();
+                            thinking = Some(start_pondering(&bot.engine, &bot.config.engine, t, expected, spent));
+                        }
                     }
                 }
             }
-            Ok(GameEvent::Other) => {}
-            Err(e) => eprintln!("Error parsing game event: {e}. Raw line = {line_str}"),
         }
     }
 
-    // Remove this game from "active games"
-    {
-        let mut ag = active_games_ref.lock().await;
-        ag.retain(|id| id != game_id);
+    // The game is over (or the stream closed), stop thinking about it. Errors above
+    // get the same from dropping `thinking`.
+    drop(thinking.take());
+    if let (Some(t), Some(archive)) = (&mut tracker, &bot.archive) {
+        match &t.final_status {
+            Some(status) => {
+                t.pgn.set_tag("Termination", status.as_str());
+                match archive.save(&t.game_id, &t.pgn) {
+                    Ok(path) => info!("Game {} saved to {}", t.game_id, path.display()),
+                    Err(e) => eprintln!("Could not save game {} to {}: {e}", t.game_id, archive.dir().display()),
+                }
+            }
+            None => info!("Game {} did not finish, not archiving it", t.game_id),
+        }
     }
 
     Ok(())
 }
 
 
-async fn try_play_move(
-    client: &Client,
-    token: &str,
-    tracker: &mut GameTracker,
-) -> Result<(), Box<dyn Error>> {
-    if tracker.board.status() == BoardStatus::Ongoing {
-        if let Some(chosen_move) = best_move_iterative(&tracker.board, 5) {
-            let uci = format_move_as_uci(chosen_move);
-            let url = format!(
-                "https://lichess.org/api/bot/game/{}/move/{}",
-                tracker.game_id, uci
-            );
+/// A search for our next move running in the background.
+struct PendingMove {
+    // The position being searched
+    board: Board,
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    result: JoinHandle<Option<SearchInfo>>,
+    // When pondering: the position before the reply we expect, and what turns the
+    // search into the real one once the opponent plays it
+    ponder: Option<(Board, Arc<PonderHit>)>,
+}
 
-            //  Add delay here to prevent rate limiting 
-            tokio::time::sleep(Duration::from_millis(100)).await;
+// Nobody waits for the result any more, and a ponder search has no clock to end it.
+impl Drop for PendingMove {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
 
-            println!("Playing move {uci} for game {}", tracker.game_id);
-            let resp = client.post(url).bearer_auth(token).send().await?;
+impl PendingMove {
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
 
-            if resp.status().is_success() {
-                tracker.board = tracker.board.make_move_new(chosen_move);
-            } else {
-                eprintln!(
-                    "Move {uci} was rejected for game {}: {}",
-                    tracker.game_id,
-                    resp.text().await?
-                );
-            }
+    /// Still waiting for the opponent to play the move we ponder on.
+    fn is_pondering(&self) -> bool {
+        self.ponder.as_ref().is_some_and(|(_, hit)| !hit.is_hit())
+    }
+
+    fn ponder_hit(&mut self) {
+        if let Some((_, hit)) = &self.ponder {
+            hit.hit();
+            // Our clock starts now, and so does the time in the PGN comment
+            self.started = Instant::now();
         }
     }
-    Ok(())
+
+    /// Whether the search is any use in `board`.
+    fn fits(&self, board: &Board) -> bool {
+        self.board == *board || (self.is_pondering() && self.ponder.as_ref().is_some_and(|(from, _)| from == board))
+    }
 }
 
-async fn accept_challenge(client: &Client, token: &str, challenge_id: &str) -> Result<(), Box<dyn Error>> {
-    let url = format!("https://lichess.org/api/challenge/{challenge_id}/accept");
-    let resp = client.post(&url).bearer_auth(token).send().await?;
-    if !resp.status().is_success() {
-        eprintln!(
-            "Failed to accept challenge {challenge_id}: {}",
-            resp.text().await?
-        );
+fn is_our_turn(tracker: &GameTracker) -> bool {
+    tracker.board.side_to_move() == tracker.my_color && tracker.board.status() == BoardStatus::Ongoing
+}
+
+fn search_limits(settings: &EngineSettings, clock: Option<&Clock>, moves_played: u32) -> SearchLimits {
+    let time = match clock {
+        Some(clock) => TimeManager::new(Duration::from_millis(settings.move_overhead_ms))
+            .allocate(clock, moves_played),
+        None => TimeBudget::fixed(Duration::from_millis(settings.no_clock_move_time_ms)),
+    };
+    SearchLimits {
+        max_depth: settings.max_depth,
+        time: Some(time),
+        ..Default::default()
     }
-    Ok(())
 }
 
-async fn stream_endpoint(
-    client: &Client,
-    token: &str,
-    url: &str,
-) -> Result<impl Stream<Item = Result<String, std::io::Error>>, Box<dyn Error>> {
-    let resp = client.get(url).bearer_auth(token).send().await?.error_for_status()?;
-    let byte_stream = resp.bytes_stream().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
-    let line_stream = FramedRead::new(StreamReader::new(byte_stream), LinesCodec::new())
-        .map(|res: Result<String, LinesCodecError>| {
-            res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-        });
-    Ok(line_stream)
+fn start_thinking(engine: &Arc<Engine>, settings: &EngineSettings, tracker: &GameTracker) -> PendingMove {
+    let limits = search_limits(settings, tracker.clock.as_ref(), tracker.moves_played);
+    let handle = engine.start_search(tracker.board, tracker.history.clone(), limits, |_| {});
+    PendingMove {
+        board: tracker.board,
+        started: Instant::now(),
+        stop: handle.stop_flag(),
+        result: spawn_blocking(move || handle.wait()),
+        ponder: None,
+    }
 }
 
-fn apply_uci_moves(board: &mut Board, moves_str: &str) {
-    if moves_str.is_empty() {
-        return;
+/// Search the position after `expected`, the opponent's reply to the move we just
+/// played after thinking for `spent`. The time budget only starts to count on a
+/// ponder hit.
+fn start_pondering(
+    engine: &Arc<Engine>,
+    settings: &EngineSettings,
+    tracker: &GameTracker,
+    expected: ChessMove,
+    spent: Duration,
+) -> PendingMove {
+    // Our clock after the move we just played, Lichess only tells us with the reply
+    let clock = tracker.clock.map(|clock| Clock {
+        remaining: clock.remaining.saturating_sub(spent) + clock.increment,
+        ..clock
+    });
+    let hit = Arc::new(PonderHit::default());
+    let limits = SearchLimits {
+        ponder: Some(Arc::clone(&hit)),
+        ..search_limits(settings, clock.as_ref(), tracker.moves_played + 1)
+    };
+    let mut history = tracker.history.clone();
+    history.push(&tracker.board, expected);
+    let board = tracker.board.make_move_new(expected);
+    let handle = engine.start_search(board, history, limits, |_| {});
+    PendingMove {
+        board,
+        started: Instant::now(),
+        stop: handle.stop_flag(),
+        result: spawn_blocking(move || handle.wait()),
+        ponder: Some((tracker.board, hit)),
     }
-    for mv in moves_str.split_whitespace() {
-        if let Ok(chess_move) = parse_uci_move(board, mv) {
-            *board = board.make_move_new(chess_move);
+}
+
+/// Send our move, and keep it if Lichess takes it. Returns whether it did.
+async fn play_move(
+    lichess: &LichessClient,
+    tracker: &mut GameTracker,
+    chosen_move: ChessMove,
+    comment: Option<String>,
+) -> Result<bool, LichessError> {
+    let uci = format_move_as_uci(chosen_move);
+    info!("Playing move {uci} for game {}", tracker.game_id);
+    match lichess.make_move(&tracker.game_id, &uci).await {
+        Ok(()) => {
+            tracker.history.push(&tracker.board, chosen_move);
+            tracker.board = tracker.board.make_move_new(chosen_move);
+            tracker.moves.push(uci);
+            tracker.pgn.push(chosen_move, comment);
+            Ok(true)
+        }
+        Err(e @ LichessError::Status { .. }) => {
+            eprintln!("Move {uci} was rejected for game {}: {e}", tracker.game_id);
+            Ok(false)
         }
+        Err(e) => Err(e),
     }
 }
 
-fn parse_uci_move(board: &Board, uci: &str) -> Result<ChessMove, ()> {
-    if uci.len() < 4 {
-        return Err(());
+/// Make the moves of `pgn` match the UCI `moves` Lichess sent, keeping the comments
+/// of the moves that are still there.
+fn sync_pgn(pgn: &mut PgnGame, moves: &str) {
+    let mut board = pgn.start;
+    for (ply, uci) in moves.split_whitespace().enumerate() {
+        let Ok(mv) = parse_uci_move(&board, uci) else { break };
+        if pgn.moves.get(ply).is_none_or(|played| played.mv != mv) {
+            pgn.moves.truncate(ply);
+            pgn.push(mv, None);
+        }
+        board = board.make_move_new(mv);
     }
+    // A takeback
+    pgn.moves.truncate(moves.split_whitespace().count());
+}
 
-    let (src_str, dst_str) = (&uci[0..2], &uci[2..4]);
-    let promo_char = uci.chars().nth(4);
-
-    let src_sq = Square::from_str(src_str).map_err(|_| ())?;
-    let dst_sq = Square::from_str(dst_str).map_err(|_| ())?;
-    let promotion_piece = match promo_char {
-        Some('q') => Some(Piece::Queen),
-        Some('r') => Some(Piece::Rook),
-        Some('b') => Some(Piece::Bishop),
-        Some('n') => Some(Piece::Knight),
-        None => None,
-        _ => return Err(()),
-    };
+/// Exponential backoff between reconnects: 1s, 2s, 4s... up to a minute. Each delay
+/// is randomly cut by up to half, so we don't come back in lockstep with other clients.
+#[derive(Default)]
+struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(60);
 
-    for legal_mv in MoveGen::new_legal(board) {
-        if legal_mv.get_source() == src_sq
-            && legal_mv.get_dest() == dst_sq
-            && legal_mv.get_promotion() == promotion_piece
-        {
-            return Ok(legal_mv);
+    fn next_delay(&mut self) -> Duration {
+        let delay = Self::INITIAL.saturating_mul(1 << self.failures.min(6)).min(Self::MAX);
+        self.failures += 1;
+        delay.mul_f64(rand::rng().random_range(0.5..=1.0))
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+enum StreamKind {
+    Events,
+    Game(String),
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamKind::Events => write!(f, "event stream"),
+            StreamKind::Game(game_id) => write!(f, "stream of game {game_id}"),
+        }
+    }
+}
+
+/// Lines of the event stream or a game stream, reconnecting with backoff whenever the
+/// connection drops. The receiver closes only when Lichess refuses the stream for
+/// good (a bad token, a game that doesn't exist), and the connection goes away
+/// once the receiver is dropped.
+fn spawn_stream(lichess: Arc<LichessClient>, kind: StreamKind) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel(64);
+    spawn(async move {
+        let mut backoff = Backoff::default();
+        loop {
+            let connection = match &kind {
+                StreamKind::Events => lichess.stream_events().await,
+                StreamKind::Game(game_id) => lichess.stream_game(game_id).await,
+            };
+            let cause = match connection {
+                Ok(mut lines) => loop {
+                    let line = tokio::select! {
+                        line = lines.next() => line,
+                        _ = sender.closed() => return,
+                    };
+                    match line {
+                        Some(Ok(line)) => {
+                            // Lichess sends a keep-alive line every few seconds, so this
+                            // resets the backoff as soon as a connection works.
+                            backoff.reset();
+                            if sender.send(line).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Err(e)) => break e.to_string(),
+                        None => break "closed by Lichess".to_string(),
+                    }
+                },
+                Err(e) if e.is_permanent() => {
+                    eprintln!("Could not open the {kind}: {e}, giving up");
+                    return;
+                }
+                Err(e) => e.to_string(),
+            };
+            let delay = backoff.next_delay();
+            eprintln!("The {kind} disconnected ({cause}), reconnecting in {:.1}s", delay.as_secs_f64());
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = sender.closed() => return,
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The next delay, checked against the jitter bounds of `base` seconds.
+    fn next_delay_of(backoff: &mut Backoff, base: u64) {
+        let delay = backoff.next_delay();
+        let base = Duration::from_secs(base);
+        assert!(delay >= base / 2 && delay <= base, "{delay:?} isn't within half of {base:?}");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_minute() {
+        let mut backoff = Backoff::default();
+        for base in [1, 2, 4, 8, 16, 32, 60, 60, 60] {
+            next_delay_of(&mut backoff, base);
         }
+        // A long outage doesn't overflow
+        backoff.failures = 100;
+        next_delay_of(&mut backoff, 60);
     }
-    Err(())
-}
-
-fn format_move_as_uci(chess_move: ChessMove) -> String {
-    format!(
-        "{}{}{}",
-        chess_move.get_source(),
-        chess_move.get_dest(),
-        match chess_move.get_promotion() {
-            Some(Piece::Knight) => "n",
-            Some(Piece::Bishop) => "b",
-            Some(Piece::Rook) => "r",
-            Some(Piece::Queen) => "q",
-            _ => "",
+
+    #[test]
+    fn backoff_starts_over_after_a_connection_works() {
+        let mut backoff = Backoff::default();
+        for base in [1, 2, 4] {
+            next_delay_of(&mut backoff, base);
         }
-    )
+        backoff.reset();
+        next_delay_of(&mut backoff, 1);
+        next_delay_of(&mut backoff, 2);
+    }
 }
@@ -0,0 +1,245 @@
+use crate::engine::GameHistory;
+use chess::{Board, BoardStatus, ChessMove, MoveGen, Piece, Square};
+use std::{error::Error, fmt, str::FromStr};
+
+/// Why a move string could not be turned into a move on the board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// The string isn't a move at all, e.g. "e2e" or "e7e8k".
+    Malformed(String),
+    /// The string is a move, but not a legal one in this position.
+    Illegal(String),
+    /// A SAN move that fits more than one legal move, e.g. "Nd2" with both knights able to go there.
+    Ambiguous(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::Malformed(mv) => write!(f, "malformed move {mv:?}"),
+            MoveError::Illegal(mv) => write!(f, "illegal move {mv:?}"),
+            MoveError::Ambiguous(mv) => write!(f, "ambiguous move {mv:?}"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+/// Play the space separated UCI moves on `board`, recording them in `history`. Stops
+/// at the first move that can't be played, leaving the moves before it on the board.
+pub fn apply_uci_moves(board: &mut Board, history: &mut GameHistory, moves_str: &str) -> Result<(), MoveError> {
+    for mv in moves_str.split_whitespace() {
+        let chess_move = parse_uci_move(board, mv)?;
+        history.push(board, chess_move);
+        *board = board.make_move_new(chess_move);
+    }
+    Ok(())
+}
+
+pub fn parse_uci_move(board: &Board, uci: &str) -> Result<ChessMove, MoveError> {
+    let malformed = || MoveError::Malformed(uci.to_string());
+    if uci.len() < 4 || uci.len() > 5 || !uci.is_ascii() {
+        return Err(malformed());
+    }
+
+    let (src_str, dst_str) = (&uci[0..2], &uci[2..4]);
+    let promo_char = uci.chars().nth(4);
+
+    let src_sq = Square::from_str(src_str).map_err(|_| malformed())?;
+    let dst_sq = Square::from_str(dst_str).map_err(|_| malformed())?;
+    let promotion_piece = match promo_char {
+        Some('q') => Some(Piece::Queen),
+        Some('r') => Some(Piece::Rook),
+        Some('b') => Some(Piece::Bishop),
+        Some('n') => Some(Piece::Knight),
+        None => None,
+        _ => return Err(malformed()),
+    };
+
+    for legal_mv in MoveGen::new_legal(board) {
+        if legal_mv.get_source() == src_sq
+            && legal_mv.get_dest() == dst_sq
+            && legal_mv.get_promotion() == promotion_piece
+        {
+            return Ok(legal_mv);
+        }
+    }
+    Err(MoveError::Illegal(uci.to_string()))
+}
+
+pub fn format_move_as_uci(chess_move: ChessMove) -> String {
+    format!(
+        "{}{}{}",
+        chess_move.get_source(),
+        chess_move.get_dest(),
+        match chess_move.get_promotion() {
+            Some(Piece::Knight) => "n",
+            Some(Piece::Bishop) => "b",
+            Some(Piece::Rook) => "r",
+            Some(Piece::Queen) => "q",
+            _ => "",
+        }
+    )
+}
+
+/// Format a line of moves, e.g. a principal variation, as space separated UCI moves.
+pub fn format_moves_as_uci(moves: &[ChessMove]) -> String {
+    moves
+        .iter()
+        .map(|&mv| format_move_as_uci(mv))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K",
+    }
+}
+
+fn is_castling(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_source()) == Some(Piece::King)
+        && mv.get_source().get_file().to_index().abs_diff(mv.get_dest().get_file().to_index()) == 2
+}
+
+/// Parse a move in standard algebraic notation ("Nf3", "exd5", "O-O", "e8=Q+").
+/// Check marks and annotations like "!?" are ignored.
+pub fn parse_san(board: &Board, san: &str) -> Result<ChessMove, MoveError> {
+    let malformed = || MoveError::Malformed(san.to_string());
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    if !text.is_ascii() || text.is_empty() {
+        return Err(malformed());
+    }
+
+    let legal: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+    let pick = |candidates: Vec<ChessMove>| match candidates.len() {
+        0 => Err(MoveError::Illegal(san.to_string())),
+        1 => Ok(candidates[0]),
+        _ => Err(MoveError::Ambiguous(san.to_string())),
+    };
+
+    let kingside = matches!(text, "O-O" | "0-0");
+    if kingside || matches!(text, "O-O-O" | "0-0-0") {
+        return pick(
+            legal
+                .into_iter()
+                .filter(|&mv| is_castling(board, mv) && (mv.get_dest().get_file() > mv.get_source().get_file()) == kingside)
+                .collect(),
+        );
+    }
+
+    let (piece, rest) = match text.as_bytes()[0] {
+        b'N' => (Piece::Knight, &text[1..]),
+        b'B' => (Piece::Bishop, &text[1..]),
+        b'R' => (Piece::Rook, &text[1..]),
+        b'Q' => (Piece::Queen, &text[1..]),
+        b'K' => (Piece::King, &text[1..]),
+        _ => (Piece::Pawn, text),
+    };
+
+    // Promotion: "e8=Q", or "e8Q" as some programs write it.
+    let (rest, promotion) = match rest.as_bytes() {
+        [.., b'=', p] | [.., b'1'..=b'8', p] if piece == Piece::Pawn && p.is_ascii_alphabetic() => {
+            let promotion = match p.to_ascii_uppercase() {
+                b'N' => Piece::Knight,
+                b'B' => Piece::Bishop,
+                b'R' => Piece::Rook,
+                b'Q' => Piece::Queen,
+                _ => return Err(malformed()),
+            };
+            (rest[..rest.len() - 1].trim_end_matches('='), Some(promotion))
+        }
+        _ => (rest, None),
+    };
+
+    // What is left is [from file][from rank][x]<destination>
+    if rest.len() < 2 {
+        return Err(malformed());
+    }
+    let dest = Square::from_str(&rest[rest.len() - 2..]).map_err(|_| malformed())?;
+    let from = rest[..rest.len() - 2].trim_end_matches('x');
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in from.chars() {
+        match c {
+            'a'..='h' if from_file.is_none() => from_file = Some(c as usize - 'a' as usize),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(c as usize - '1' as usize),
+            _ => return Err(malformed()),
+        }
+    }
+
+    pick(
+        legal
+            .into_iter()
+            .filter(|&mv| {
+                board.piece_on(mv.get_source()) == Some(piece)
+                    && mv.get_dest() == dest
+                    && mv.get_promotion() == promotion
+                    && from_file.is_none_or(|file| mv.get_source().get_file().to_index() == file)
+                    && from_rank.is_none_or(|rank| mv.get_source().get_rank().to_index() == rank)
+            })
+            .collect(),
+    )
+}
+
+/// Format a legal move in standard algebraic notation, with "+" or "#" when it
+/// gives check or mate.
+pub fn format_move_as_san(board: &Board, mv: ChessMove) -> String {
+    let piece = board.piece_on(mv.get_source()).unwrap();
+    let capture = board.piece_on(mv.get_dest()).is_some()
+        || (piece == Piece::Pawn && mv.get_source().get_file() != mv.get_dest().get_file());
+
+    let mut san = if is_castling(board, mv) {
+        if mv.get_dest().get_file() > mv.get_source().get_file() { "O-O" } else { "O-O-O" }.to_string()
+    } else {
+        let mut san = piece_letter(piece).to_string();
+        if piece == Piece::Pawn {
+            if capture {
+                san.push_str(&mv.get_source().to_string()[..1]);
+            }
+        } else {
+            // Only say where the piece comes from if another one of the same kind
+            // could go to the same square.
+            let others: Vec<Square> = MoveGen::new_legal(board)
+                .filter(|other| {
+                    other.get_dest() == mv.get_dest()
+                        && other.get_source() != mv.get_source()
+                        && board.piece_on(other.get_source()) == Some(piece)
+                })
+                .map(|other| other.get_source())
+                .collect();
+            let source = mv.get_source().to_string();
+            if !others.is_empty() {
+                if others.iter().all(|sq| sq.get_file() != mv.get_source().get_file()) {
+                    san.push_str(&source[..1]);
+                } else if others.iter().all(|sq| sq.get_rank() != mv.get_source().get_rank()) {
+                    san.push_str(&source[1..]);
+                } else {
+                    san.push_str(&source);
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&mv.get_dest().to_string());
+        if let Some(promotion) = mv.get_promotion() {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+        san
+    };
+
+    let after = board.make_move_new(mv);
+    if after.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if after.checkers().popcnt() > 0 {
+        san.push('+');
+    }
+    san
+}
@@ -0,0 +1,327 @@
+use chess::{Board, Color, Piece};
+use std::cell::RefCell;
+
+// Bonuses and penalties as (middlegame, endgame) pairs.
+const DOUBLED: (i32, i32) = (-10, -25);
+const ISOLATED: (i32, i32) = (-12, -15);
+const BACKWARD: (i32, i32) = (-8, -10);
+
+// Indexed by rank from the pawn's own side, 0 = first rank.
+const CONNECTED: [i32; 8] = [0, 5, 7, 10, 15, 25, 40, 0];
+const PASSED_MG: [i32; 8] = [0, 5, 10, 15, 25, 45, 70, 0];
+const PASSED_EG: [i32; 8] = [0, 10, 15, 30, 50, 80, 120, 0];
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+// Entries per thread, must be a power of two.
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+/// Everything about the pawn structure that only depends on the pawns.
+#[derive(Clone, Copy)]
+struct PawnEntry {
+    key: u64,
+    mg: i32,
+    eg: i32,
+    // Passed pawns of White and Black.
+    passed: [u64; 2],
+}
+
+thread_local! {
+    // Every search thread gets its own table, so lookups need no synchronization.
+    // Pawn structure changes rarely, so nearly every lookup is a hit.
+    static PAWN_TABLE: RefCell<Vec<Option<PawnEntry>>> = RefCell::new(vec![None; PAWN_TABLE_SIZE]);
+}
+
+// Zobrist keys for a pawn of each color on each square.
+const PAWN_KEYS: [[u64; 64]; 2] = pawn_keys();
+
+const fn pawn_keys() -> [[u64; 64]; 2] {
+    // splitmix64, so the keys are fixed at compile time
+    let mut keys = [[0; 64]; 2];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut color = 0;
+    while color < 2 {
+        let mut sq = 0;
+        while sq < 64 {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            keys[color][sq] = z ^ (z >> 31);
+            sq += 1;
+        }
+        color += 1;
+    }
+    keys
+}
+
+fn pawns_of(board: &Board, color: Color) -> u64 {
+    (board.pieces(Piece::Pawn) & board.color_combined(color)).0
+}
+
+/// Hash of the pawns only, so positions with the same pawn structure share an entry.
+pub(crate) fn pawn_hash(board: &Board) -> u64 {
+    let mut hash = 0;
+    for (color_idx, color) in [Color::White, Color::Black].into_iter().enumerate() {
+        let mut pawns = pawns_of(board, color);
+        while pawns != 0 {
+            hash ^= PAWN_KEYS[color_idx][pawns.trailing_zeros() as usize];
+            pawns &= pawns - 1;
+        }
+    }
+    hash
+}
+
+fn file_mask(file: usize) -> u64 {
+    FILE_A << file
+}
+
+fn adjacent_files(file: usize) -> u64 {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+/// All squares on ranks strictly in front of `rank`, seen from `color`.
+fn ranks_ahead(color: Color, rank: usize) -> u64 {
+    match color {
+        Color::White if rank < 7 => !0 << (8 * (rank + 1)),
+        Color::Black => (1 << (8 * rank)) - 1,
+        _ => 0,
+    }
+}
+
+fn relative_rank(color: Color, rank: usize) -> usize {
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+/// The square one step forward, seen from `color`. `None` on the last rank, where a
+/// pawn can only be in a position set up by hand.
+fn forward(color: Color, sq: usize) -> Option<usize> {
+    match color {
+        Color::White => (sq < 56).then_some(sq + 8),
+        Color::Black => sq.checked_sub(8),
+    }
+}
+
+/// Squares attacked by the pawns in `pawns` of `color`.
+fn pawn_attacks(color: Color, pawns: u64) -> u64 {
+    let not_a = !FILE_A;
+    let not_h = !(FILE_A << 7);
+    match color {
+        Color::White => ((pawns & not_a) << 7) | ((pawns & not_h) << 9),
+        Color::Black => ((pawns & not_a) >> 9) | ((pawns & not_h) >> 7),
+    }
+}
+
+/// Score one side's pawns, White's point of view is applied by the caller.
+fn score_side(color: Color, own: u64, enemy: u64) -> (i32, i32, u64) {
+    let mut mg = 0;
+    let mut eg = 0;
+    let mut passed = 0;
+
+    for file in 0..8 {
+        let count = (own & file_mask(file)).count_ones() as i32;
+        if count > 1 {
+            mg += DOUBLED.0 * (count - 1);
+            eg += DOUBLED.1 * (count - 1);
+        }
+    }
+
+    let own_attacks = pawn_attacks(color, own);
+    let enemy_attacks = pawn_attacks(!color, enemy);
+
+    let mut pawns = own;
+    while pawns != 0 {
+        let sq = pawns.trailing_zeros() as usize;
+        pawns &= pawns - 1;
+        let (file, rank) = (sq % 8, sq / 8);
+        let bit = 1u64 << sq;
+        let ahead = ranks_ahead(color, rank);
+        let neighbours = own & adjacent_files(file);
+
+        let supported = own_attacks & bit != 0;
+        let phalanx = neighbours & (0xFF << (8 * rank)) != 0;
+        if supported || phalanx {
+            let bonus = CONNECTED[relative_rank(color, rank)];
+            mg += bonus;
+            eg += bonus;
+        }
+
+        if neighbours == 0 {
+            mg += ISOLATED.0;
+            eg += ISOLATED.1;
+        } else if !supported
+            && neighbours & !ahead == 0
+            && forward(color, sq).is_some_and(|front| enemy_attacks & (1u64 << front) != 0)
+        {
+            // Every neighbour is already further up the board, so none can come to
+            // defend this pawn, and it can't advance safely either.
+            mg += BACKWARD.0;
+            eg += BACKWARD.1;
+        }
+
+        let front_span = ahead & (file_mask(file) | adjacent_files(file));
+        if enemy & front_span == 0 && own & ahead & file_mask(file) == 0 {
+            passed |= bit;
+        }
+    }
+
+    (mg, eg, passed)
+}
+
+fn pawn_entry(board: &Board, key: u64) -> PawnEntry {
+    let white = pawns_of(board, Color::White);
+    let black = pawns_of(board, Color::Black);
+    let (white_mg, white_eg, white_passed) = score_side(Color::White, white, black);
+    let (black_mg, black_eg, black_passed) = score_side(Color::Black, black, white);
+    PawnEntry {
+        key,
+        mg: white_mg - black_mg,
+        eg: white_eg - black_eg,
+        passed: [white_passed, black_passed],
+    }
+}
+
+/// Passed pawn bonuses, scaled down when something stands in the pawn's way.
+fn passed_pawn_bonus(board: &Board, color: Color, mut passed: u64) -> (i32, i32) {
+    let occupied = board.combined().0;
+    let mut mg = 0;
+    let mut eg = 0;
+    while passed != 0 {
+        let sq = passed.trailing_zeros() as usize;
+        passed &= passed - 1;
+        let rank = relative_rank(color, sq / 8);
+        let (mut pawn_mg, mut pawn_eg) = (PASSED_MG[rank], PASSED_EG[rank]);
+
+        let path = ranks_ahead(color, sq / 8) & file_mask(sq % 8);
+        if forward(color, sq).is_some_and(|front| occupied & (1u64 << front) != 0) {
+            // Blockaded: it can't move at all right now.
+            pawn_mg /= 2;
+            pawn_eg /= 2;
+        } else if occupied & path != 0 {
+            pawn_mg = pawn_mg * 3 / 4;
+            pawn_eg = pawn_eg * 3 / 4;
+        }
+        mg += pawn_mg;
+        eg += pawn_eg;
+    }
+    (mg, eg)
+}
+
+/// Pawn structure score as (middlegame, endgame) from White's point of view.
+pub(crate) fn evaluate_pawns(board: &Board) -> (i32, i32) {
+    let key = pawn_hash(board);
+    let entry = PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let slot = &mut table[key as usize & (PAWN_TABLE_SIZE - 1)];
+        match slot {
+            Some(entry) if entry.key == key => *entry,
+            _ => {
+                let entry = pawn_entry(board, key);
+                *slot = Some(entry);
+                entry
+            }
+        }
+    });
+
+    let (white_mg, white_eg) = passed_pawn_bonus(board, Color::White, entry.passed[0]);
+    let (black_mg, black_eg) = passed_pawn_bonus(board, Color::Black, entry.passed[1]);
+    (
+        entry.mg + white_mg - black_mg,
+        entry.eg + white_eg - black_eg,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn squares(names: &[&str]) -> u64 {
+        names.iter().map(|name| 1u64 << chess::Square::from_str(name).unwrap().to_index()).fold(0, |a, b| a | b)
+    }
+
+    /// `score_side` for White, checked against the same pawns mirrored for Black.
+    fn score_white(own: &[&str], enemy: &[&str]) -> (i32, i32, u64) {
+        let (own, enemy) = (squares(own), squares(enemy));
+        let white = score_side(Color::White, own, enemy);
+        let (mg, eg, passed) = score_side(Color::Black, own.swap_bytes(), enemy.swap_bytes());
+        assert_eq!(white, (mg, eg, passed.swap_bytes()), "Black scores the mirrored pawns differently");
+        white
+    }
+
+    #[test]
+    fn isolated_pawns() {
+        assert_eq!(score_white(&["a2", "c2"], &["a7", "c7"]), (2 * ISOLATED.0, 2 * ISOLATED.1, 0));
+    }
+
+    #[test]
+    fn connected_pawns() {
+        // Side by side
+        assert_eq!(score_white(&["d4", "e4"], &["d6", "e6"]), (2 * CONNECTED[3], 2 * CONNECTED[3], 0));
+        // Defended, further up the board is worth more
+        assert_eq!(score_white(&["d5", "e4"], &["d7", "e7"]).0, CONNECTED[4]);
+    }
+
+    #[test]
+    fn backward_pawns() {
+        // e3 can't be defended by d4 and d5 stops it from advancing
+        let (mg, eg, _) = score_white(&["d4", "e3"], &["d5"]);
+        assert_eq!((mg, eg), (CONNECTED[3] + BACKWARD.0, CONNECTED[3] + BACKWARD.1));
+        // Without the enemy pawn it can just advance
+        let (mg, eg, _) = score_white(&["d4", "e3"], &["d6"]);
+        assert_eq!((mg, eg), (CONNECTED[3], CONNECTED[3]));
+    }
+
+    #[test]
+    fn passed_pawns() {
+        assert_eq!(score_white(&["e5"], &["a7"]).2, squares(&["e5"]));
+        // Stopped by a pawn in front or next to its path, but not behind it
+        assert_eq!(score_white(&["e5"], &["e7"]).2, 0);
+        assert_eq!(score_white(&["e5"], &["f6"]).2, 0);
+        assert_eq!(score_white(&["e5"], &["d4"]).2, squares(&["e5"]));
+        // Only the front one of doubled pawns
+        assert_eq!(score_white(&["e5", "e3"], &[]).2, squares(&["e5"]));
+    }
+
+    #[test]
+    fn mirrored_positions_score_the_opposite() {
+        let board = Board::from_str("4k3/pp3p2/4p3/3P4/2P3P1/8/P7/4K3 w - - 0 1").unwrap();
+        let mirrored = Board::from_str("4k3/p7/8/2p3p1/3p4/4P3/PP3P2/4K3 b - - 0 1").unwrap();
+        let (mg, eg) = evaluate_pawns(&board);
+        assert_eq!(evaluate_pawns(&mirrored), (-mg, -eg));
+        assert_ne!((mg, eg), (0, 0));
+    }
+
+    #[test]
+    fn pawns_on_the_last_rank() {
+        // Not legal, but UCI can set it up and it must not crash the evaluation
+        let own = squares(&["h8", "b7"]);
+        let (_, _, passed) = score_side(Color::White, own, squares(&["a1"]));
+        assert_eq!(passed, own);
+        let board = Board::from_str("7P/1P6/8/8/8/8/k7/p3K3 w - - 0 1").unwrap();
+        let (mg, eg) = passed_pawn_bonus(&board, Color::White, passed);
+        assert_eq!((mg, eg), (PASSED_MG[6] + PASSED_MG[7], PASSED_EG[6] + PASSED_EG[7]));
+        evaluate_pawns(&board);
+    }
+
+    #[test]
+    fn the_table_is_keyed_by_pawns_only() {
+        let board = Board::from_str("r3k3/pp3p2/4p3/3P4/2P3P1/8/P7/4K2R w - - 0 1").unwrap();
+        let same_pawns = Board::from_str("4k3/pp3p2/4p3/3P4/2P3P1/8/P5Q1/4K3 b - - 0 1").unwrap();
+        let other_pawns = Board::from_str("r3k3/pp3p2/4p3/3P4/2P3P1/P7/8/4K2R w - - 0 1").unwrap();
+        assert_eq!(pawn_hash(&board), pawn_hash(&same_pawns));
+        assert_ne!(pawn_hash(&board), pawn_hash(&other_pawns));
+
+        // Fresh, after the slot was taken by other pawns, and straight from the table
+        let fresh = evaluate_pawns(&board);
+        evaluate_pawns(&other_pawns);
+        assert_eq!(evaluate_pawns(&board), fresh);
+        assert_eq!(evaluate_pawns(&board), fresh);
+        assert_eq!(evaluate_pawns(&same_pawns), fresh);
+    }
+}
@@ -0,0 +1,29 @@
+//! Perft: count every move sequence of a given length, to check move generation (and
+//! anything that rebuilds boards from moves) against the well known numbers.
+use crate::notation::format_move_as_uci;
+use chess::{Board, ChessMove, MoveGen};
+
+/// Number of legal move sequences `depth` plies long from `board`.
+pub fn perft(board: &Board, depth: u32) -> u64 {
+    match depth {
+        0 => 1,
+        // The move generator knows how many moves it has without making them.
+        1 => MoveGen::new_legal(board).len() as u64,
+        _ => MoveGen::new_legal(board)
+            .map(|mv| perft(&board.make_move_new(mv), depth - 1))
+            .sum(),
+    }
+}
+
+/// [`perft`] split up by the first move, sorted by the move's UCI string so the
+/// output can be diffed against other engines. Sums to `perft(board, depth)`.
+pub fn divide(board: &Board, depth: u32) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let mut moves: Vec<(ChessMove, u64)> = MoveGen::new_legal(board)
+        .map(|mv| (mv, perft(&board.make_move_new(mv), depth - 1)))
+        .collect();
+    moves.sort_by_key(|&(mv, _)| format_move_as_uci(mv));
+    moves
+}
@@ -0,0 +1,451 @@
+//! Games in PGN, for archiving them and feeding them to analysis and tuning tools.
+use crate::engine::{SearchInfo, MATE_BOUND, MATE_SCORE};
+use crate::notation::{format_move_as_san, parse_san, MoveError};
+use chess::{Board, ChessMove, Color};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Movetext lines are wrapped before this many characters, as the PGN standard asks.
+const LINE_LENGTH: usize = 80;
+
+/// A move and the comment after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: ChessMove,
+    pub comment: Option<String>,
+}
+
+/// One game: tags, the starting position and the moves (variations are dropped).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    /// In the order they are written, the seven tag roster (Event, Site, Date, Round,
+    /// White, Black, Result) first.
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<PgnMove>,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        PgnGame::new(Board::default())
+    }
+}
+
+impl PgnGame {
+    /// A game without moves from `start`, with the seven tag roster unknown.
+    pub fn new(start: Board) -> Self {
+        let tags = [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ];
+        PgnGame {
+            tags: tags.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect(),
+            start,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Set a tag, adding it after the others if the game doesn't have it yet.
+    pub fn set_tag(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value,
+            None => self.tags.push((name.to_string(), value)),
+        }
+    }
+
+    /// "1-0", "0-1", "1/2-1/2" or "*" while the game is going on.
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+
+    /// Play `mv` (legal in the current position) at the end of the game.
+    pub fn push(&mut self, mv: ChessMove, comment: Option<String>) {
+        self.moves.push(PgnMove { mv, comment });
+    }
+
+    /// The starting position and the position after every move.
+    pub fn positions(&self) -> Vec<Board> {
+        let mut positions = vec![self.start];
+        for pgn_move in &self.moves {
+            let last = positions[positions.len() - 1];
+            positions.push(last.make_move_new(pgn_move.mv));
+        }
+        positions
+    }
+
+    /// The position at the end of the game.
+    pub fn board(&self) -> Board {
+        self.moves.iter().fold(self.start, |board, pgn_move| board.make_move_new(pgn_move.mv))
+    }
+}
+
+impl fmt::Display for PgnGame {
+    /// The game in export format, ending with an empty line so games can be appended
+    /// one after the other.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fen = self.start.to_string();
+        let mut tags = self.tags.clone();
+        if fen != STARTPOS && !tags.iter().any(|(name, _)| name == "FEN") {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+        for (name, value) in &tags {
+            writeln!(f, "[{name} \"{}\"]", value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+
+        // The fullmove number isn't part of the board, count from the FEN tag's if any.
+        let first_move = self
+            .tag("FEN")
+            .and_then(|fen| fen.split_whitespace().nth(5))
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(1);
+        let mut tokens = Vec::new();
+        let mut board = self.start;
+        let mut needs_number = true;
+        for (ply, pgn_move) in self.moves.iter().enumerate() {
+            let number = first_move + (ply + (self.start.side_to_move() == Color::Black) as usize) / 2;
+            match board.side_to_move() {
+                Color::White => tokens.push(format!("{number}.")),
+                Color::Black if needs_number => tokens.push(format!("{number}...")),
+                Color::Black => {}
+            }
+            tokens.push(format_move_as_san(&board, pgn_move.mv));
+            needs_number = false;
+            if let Some(comment) = &pgn_move.comment {
+                tokens.push(format!("{{{}}}", comment.replace('}', "")));
+                needs_number = true;
+            }
+            board = board.make_move_new(pgn_move.mv);
+        }
+        tokens.push(self.result().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() >= LINE_LENGTH {
+                writeln!(f, "{line}")?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{line}")?;
+        writeln!(f)
+    }
+}
+
+/// Why a game could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    /// A tag pair that isn't `[Name "value"]`.
+    InvalidTag(String),
+    /// The FEN tag doesn't describe a position.
+    InvalidFen(String),
+    /// A move that doesn't fit the position, `ply` halfmoves after the start.
+    InvalidMove { ply: usize, error: MoveError },
+    /// No game at all.
+    Empty,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag {tag:?}"),
+            PgnError::InvalidFen(fen) => write!(f, "invalid FEN {fen:?}"),
+            PgnError::InvalidMove { ply, error } => write!(f, "{error} at ply {}", ply + 1),
+            PgnError::Empty => write!(f, "no game found"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl FromStr for PgnGame {
+    type Err = PgnError;
+
+    /// Parse the first game in `text`.
+    fn from_str(text: &str) -> Result<Self, PgnError> {
+        parse_pgn(text).into_iter().next().unwrap_or(Err(PgnError::Empty))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    /// A move, a move number or a result.
+    Symbol(String),
+    VariationStart,
+    VariationEnd,
+}
+
+/// Split PGN text into tokens. Numeric annotation glyphs ("$1") and escaped lines
+/// ("%...") are dropped.
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => {}
+            '%' if line_start => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+                continue;
+            }
+            '[' => {
+                let mut tag = String::new();
+                let mut quoted = false;
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' if !quoted => break,
+                        '"' if !escaped => quoted = !quoted,
+                        _ => {}
+                    }
+                    escaped = c == '\\' && !escaped;
+                    tag.push(c);
+                }
+                tokens.push(parse_tag(&tag)?);
+            }
+            '{' => tokens.push(Token::Comment(chars.by_ref().take_while(|&c| c != '}').collect())),
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment));
+                line_start = true;
+                continue;
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                while chars.next_if(|c| c.is_ascii_digit()).is_some() {}
+            }
+            _ => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]{}();$".contains(*c)) {
+                    symbol.push(c);
+                }
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+/// `Name "value"`, the inside of a tag pair.
+fn parse_tag(tag: &str) -> Result<Token, PgnError> {
+    let invalid = || PgnError::InvalidTag(tag.to_string());
+    let (name, value) = tag.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let value = value.trim().strip_prefix('"').and_then(|v| v.strip_suffix('"')).ok_or_else(invalid)?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(c),
+        }
+    }
+    Ok(Token::Tag(name.to_string(), unescaped))
+}
+
+/// A game being read.
+struct GameBuilder {
+    game: PgnGame,
+    // The position after the moves so far, `None` before the first move.
+    board: Option<Board>,
+    // How deep in variations we are; their moves are skipped.
+    variation_depth: usize,
+    error: Option<PgnError>,
+}
+
+impl GameBuilder {
+    fn new() -> Self {
+        let mut game = PgnGame::default();
+        game.tags.clear();
+        GameBuilder {
+            game,
+            board: None,
+            variation_depth: 0,
+            error: None,
+        }
+    }
+
+    fn has_movetext(&self) -> bool {
+        self.board.is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.game.tags.is_empty() && !self.has_movetext() && self.error.is_none()
+    }
+
+    fn board(&mut self) -> Result<Board, PgnError> {
+        if let Some(board) = self.board {
+            return Ok(board);
+        }
+        if let Some(fen) = self.game.tag("FEN") {
+            self.game.start = Board::from_str(fen).map_err(|_| PgnError::InvalidFen(fen.to_string()))?;
+        }
+        self.board = Some(self.game.start);
+        Ok(self.game.start)
+    }
+
+    fn play(&mut self, san: &str) -> Result<(), PgnError> {
+        let board = self.board()?;
+        let mv = parse_san(&board, san).map_err(|error| PgnError::InvalidMove {
+            ply: self.game.moves.len(),
+            error,
+        })?;
+        self.game.push(mv, None);
+        self.board = Some(board.make_move_new(mv));
+        Ok(())
+    }
+
+    fn add_token(&mut self, token: Token) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match token {
+            Token::VariationStart => {
+                self.variation_depth += 1;
+                Ok(())
+            }
+            Token::VariationEnd => {
+                self.variation_depth = self.variation_depth.saturating_sub(1);
+                Ok(())
+            }
+            _ if self.variation_depth > 0 => Ok(()),
+            Token::Tag(name, value) => {
+                self.game.set_tag(&name, value);
+                Ok(())
+            }
+            Token::Comment(comment) => {
+                // Comments before the first move are about the game, not a move.
+                if let Some(last) = self.game.moves.last_mut() {
+                    let comment = comment.trim();
+                    last.comment = Some(match last.comment.take() {
+                        Some(earlier) => format!("{earlier} {comment}"),
+                        None => comment.to_string(),
+                    });
+                }
+                Ok(())
+            }
+            Token::Symbol(symbol) => {
+                // "12." and "12..." are move numbers, "12.e4" is one followed by a move.
+                let san = match symbol.starts_with(|c: char| c.is_ascii_digit()) {
+                    true => symbol.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches('.'),
+                    false => &symbol,
+                };
+                if san.is_empty() {
+                    self.board().map(drop)
+                } else {
+                    self.play(san)
+                }
+            }
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    fn finish(mut self, result: Option<&str>) -> Result<PgnGame, PgnError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.board()?;
+        if let Some(result) = result {
+            if self.game.tag("Result").is_none_or(|tag| tag == "*") {
+                self.game.set_tag("Result", result);
+            }
+        }
+        Ok(self.game)
+    }
+}
+
+/// Parse every game in `text`. A game with an error doesn't stop the ones after it
+/// from being read.
+pub fn parse_pgn(text: &str) -> Vec<Result<PgnGame, PgnError>> {
+    let tokens = match tokenize(text) {
+        Ok(tokens) => tokens,
+        Err(e) => return vec![Err(e)],
+    };
+    let mut games = Vec::new();
+    let mut builder = GameBuilder::new();
+    for token in tokens {
+        match token {
+            Token::Symbol(symbol) if matches!(symbol.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") => {
+                let finished = std::mem::replace(&mut builder, GameBuilder::new());
+                games.push(finished.finish(Some(&symbol)));
+            }
+            // Tags after moves belong to the next game, the last one had no result.
+            Token::Tag(..) if builder.has_movetext() => {
+                let finished = std::mem::replace(&mut builder, GameBuilder::new());
+                games.push(finished.finish(None));
+                builder.add_token(token);
+            }
+            token => builder.add_token(token),
+        }
+    }
+    if !builder.is_empty() {
+        games.push(builder.finish(None));
+    }
+    games
+}
+
+/// The search result of a move as a PGN comment: the score from White's point of
+/// view in pawns (or moves to mate) and the depth, e.g. "[%eval 0.35,12]". `board`
+/// is the position that was searched.
+pub fn eval_comment(board: &Board, info: &SearchInfo) -> String {
+    let score = match board.side_to_move() {
+        Color::White => info.score,
+        Color::Black => -info.score,
+    };
+    let eval = if score >= MATE_BOUND {
+        format!("#{}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("#-{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("{:.2}", score as f64 / 100.0)
+    };
+    format!("[%eval {eval},{}]", info.depth)
+}
+
+/// Time spent on a move as a PGN comment, e.g. "[%emt 0:00:01.25]".
+pub fn move_time_comment(time: Duration) -> String {
+    let centis = time.as_millis() / 10;
+    let seconds = centis / 100;
+    format!("[%emt {}:{:02}:{:02}.{:02}]", seconds / 3600, seconds / 60 % 60, seconds % 60, centis % 100)
+}
+
+/// The PGN Date tag ("2024.03.17") of a Unix timestamp in milliseconds.
+pub fn format_date(unix_millis: u64) -> String {
+    // Days to a civil date, from Howard Hinnant's date algorithms.
+    let days = (unix_millis / 86_400_000) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{year:04}.{month:02}.{day:02}")
+}
@@ -1,4 +1,8 @@
-pub static PAWN_PST: [i32; 64] = [
+// Tables are laid out from White's point of view, a1..h1 first, a8..h8 last.
+// Black looks them up upside down (a8 is its a1).
+
+// Pawn PST (middlegame)
+pub static PAWN_MG_PST: [i32; 64] = [
      0,   0,   0,   0,   0,   0,   0,   0,
      5,  10,  10, -20, -20,  10,  10,   5,
      5,  -5, -10,   0,   5, -10,  -5,   5,
@@ -9,8 +13,8 @@ pub static PAWN_PST: [i32; 64] = [
      0,   0,   0,   0,   0,   0,   0,   0,
 ];
 
-// Knight PST
-pub static KNIGHT_PST: [i32; 64] = [
+// Knight PST (middlegame)
+pub static KNIGHT_MG_PST: [i32; 64] = [
     -50, -40, -30, -30, -30, -30, -40, -50,
     -40, -20,   0,   0,   0,   0, -20, -40,
     -30,   0,  10,  15,  15,  5,   0, -30,
@@ -21,8 +25,8 @@ pub static KNIGHT_PST: [i32; 64] = [
     -50, -40, -30, -30, -30, -30, -40, -50,
 ];
 
-// Bishop PST
-pub static BISHOP_PST: [i32; 64] = [
+// Bishop PST (middlegame)
+pub static BISHOP_MG_PST: [i32; 64] = [
     -20, -10, -10, -10, -10, -10, -10, -20,
     -10,   5,   0,   0,   0,   0,   5, -10,
     -10,  10,  10,  10,  10,  10,  10, -10,
@@ -33,8 +37,8 @@ pub static BISHOP_PST: [i32; 64] = [
     -20, -10, -10, -10, -10, -10, -10, -20,
 ];
 
-// Rook PST
-pub static ROOK_PST: [i32; 64] = [
+// Rook PST (middlegame)
+pub static ROOK_MG_PST: [i32; 64] = [
      0,   0,   0,   5,   5,   0,   0,   0,
     -5,   0,   0,   0,   0,   0,   0,  -5,
     -5,   0,   0,   0,   0,   0,   0,  -5,
@@ -45,8 +49,8 @@ pub static ROOK_PST: [i32; 64] = [
      0,   0,   0,   5,   5,   0,   0,   0,
 ];
 
-// Queen PST
-pub static QUEEN_PST: [i32; 64] = [
+// Queen PST (middlegame)
+pub static QUEEN_MG_PST: [i32; 64] = [
     -20, -10, -10,  -5,  -5, -10, -10, -20,
     -10,   0,   5,   0,   0,   0,   0, -10,
     -10,   5,   5,   5,   5,   5,   0, -10,
@@ -57,14 +61,86 @@ pub static QUEEN_PST: [i32; 64] = [
     -20, -10, -10,  -5,  -5, -10, -10, -20,
 ];
 
-// King PST (middlegame-ish)
-pub static KING_PST: [i32; 64] = [
+// King PST (middlegame): stay behind the pawns, preferably castled
+pub static KING_MG_PST: [i32; 64] = [
+     20,  30,  10,   0,   0,  10,  30,  20,
+     20,  20,   0,   0,   0,   0,  20,  20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+    -20, -30, -30, -40, -40, -30, -30, -20,
     -30, -40, -40, -50, -50, -40, -40, -30,
     -30, -40, -40, -50, -50, -40, -40, -30,
     -30, -40, -40, -50, -50, -40, -40, -30,
     -30, -40, -40, -50, -50, -40, -40, -30,
-    -20, -30, -30, -40, -40, -30, -30, -20,
-     20,  20,   0,   0,   0,   0,  20,  20,
-     30,  30,  20,   0,   0,  20,  30,  30,
-     50,  50,  30,   0,   0,  30,  50,  50,
+];
+
+// Pawn PST (endgame): the further the better
+pub static PAWN_EG_PST: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,   5,   5,   5,   5,   5,   5,   5,
+    10,  10,  10,  10,  10,  10,  10,  10,
+    20,  20,  20,  20,  20,  20,  20,  20,
+    35,  35,  35,  35,  35,  35,  35,  35,
+    55,  55,  55,  55,  55,  55,  55,  55,
+    80,  80,  80,  80,  80,  80,  80,  80,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+// Knight PST (endgame)
+pub static KNIGHT_EG_PST: [i32; 64] = [
+    -40, -30, -20, -20, -20, -20, -30, -40,
+    -30, -15,  -5,   0,   0,  -5, -15, -30,
+    -20,  -5,   5,  10,  10,   5,  -5, -20,
+    -20,   0,  10,  15,  15,  10,   0, -20,
+    -20,   0,  10,  15,  15,  10,   0, -20,
+    -20,  -5,   5,  10,  10,   5,  -5, -20,
+    -30, -15,  -5,   0,   0,  -5, -15, -30,
+    -40, -30, -20, -20, -20, -20, -30, -40,
+];
+
+// Bishop PST (endgame)
+pub static BISHOP_EG_PST: [i32; 64] = [
+    -15, -10, -10, -10, -10, -10, -10, -15,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -15, -10, -10, -10, -10, -10, -10, -15,
+];
+
+// Rook PST (endgame)
+pub static ROOK_EG_PST: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+    10,  10,  10,  10,  10,  10,  10,  10,
+     5,   5,   5,   5,   5,   5,   5,   5,
+];
+
+// Queen PST (endgame)
+pub static QUEEN_EG_PST: [i32; 64] = [
+    -20, -15, -10, -10, -10, -10, -15, -20,
+    -15,  -5,   0,   0,   0,   0,  -5, -15,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,  10,  15,  15,  10,   0, -10,
+    -10,   0,  10,  15,  15,  10,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -15,  -5,   0,   0,   0,   0,  -5, -15,
+    -20, -15, -10, -10, -10, -10, -15, -20,
+];
+
+// King PST (endgame): come out and centralize
+pub static KING_EG_PST: [i32; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -50, -40, -30, -20, -20, -30, -40, -50,
 ];
@@ -0,0 +1,449 @@
+//! Engine-vs-engine matches, to find out whether a change to the engine actually
+//! makes it stronger. Two [`Player`]s play every opening with both colors, several
+//! games at a time, and the score is tracked as Elo and as an SPRT (sequential
+//! probability ratio test) that can end the match as soon as the answer is clear.
+use crate::engine::{Engine, EngineConfig, GameHistory, SearchLimits};
+use chess::{Board, BoardStatus, ChessMove, Color, Piece};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Three moves into the most common openings, so the games don't all play out the same.
+pub const DEFAULT_OPENINGS: [&str; 20] = [
+    // Ruy Lopez
+    "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq -",
+    // Italian
+    "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq -",
+    // Petroff
+    "rnbqkb1r/ppp2ppp/3p1n2/4N3/4P3/8/PPPP1PPP/RNBQKB1R w KQkq -",
+    // Sicilian
+    "rnbqkbnr/pp2pppp/3p4/8/3pP3/5N2/PPP2PPP/RNBQKB1R w KQkq -",
+    // Closed Sicilian
+    "r1bqkbnr/pp1ppp1p/2n3p1/2p5/4P3/2N3P1/PPPP1P1P/R1BQKBNR w KQkq -",
+    // French
+    "rnbqkb1r/ppp2ppp/4pn2/3p4/3PP3/2N5/PPP2PPP/R1BQKBNR w KQkq -",
+    // Caro-Kann, advance
+    "rn1qkbnr/pp2pppp/2p5/3pPb2/3P4/8/PPP2PPP/RNBQKBNR w KQkq -",
+    // Pirc
+    "rnbqkb1r/ppp1pp1p/3p1np1/8/3PP3/2N5/PPP2PPP/R1BQKBNR w KQkq -",
+    // Scandinavian
+    "rnb1kbnr/ppp1pppp/8/q7/8/2N5/PPPP1PPP/R1BQKBNR w KQkq -",
+    // Queen's Gambit Declined
+    "rnbqkb1r/ppp2ppp/4pn2/3p4/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq -",
+    // Slav
+    "rnbqkb1r/pp2pppp/2p2n2/3p4/2PP4/5N2/PP2PPPP/RNBQKB1R w KQkq -",
+    // Queen's Gambit Accepted
+    "rnbqkb1r/ppp1pppp/5n2/8/2pP4/5N2/PP2PPPP/RNBQKB1R w KQkq -",
+    // King's Indian
+    "rnbqk2r/ppppppbp/5np1/8/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq -",
+    // Nimzo-Indian
+    "rnbqk2r/pppp1ppp/4pn2/8/1bPP4/2N5/PP2PPPP/R1BQKBNR w KQkq -",
+    // Queen's Indian
+    "rnbqkb1r/p1pp1ppp/1p2pn2/8/2PP4/5N2/PP2PPPP/RNBQKB1R w KQkq -",
+    // Benko Gambit
+    "rnbqkb1r/p2ppppp/5n2/1ppP4/2P5/8/PP2PPPP/RNBQKBNR w KQkq -",
+    // Dutch
+    "rnbqkb1r/ppppp2p/5np1/5p2/3P4/6P1/PPP1PPBP/RNBQK1NR w KQkq -",
+    // English, four knights
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2P5/2N2N2/PP1PPPPP/R1BQKB1R w KQkq -",
+    // Symmetrical English
+    "r1bqkb1r/pp1ppppp/2n2n2/2p5/2P5/2N2N2/PP1PPPPP/R1BQKB1R w KQkq -",
+    // Reti
+    "rnbqkb1r/pp2pppp/2p2n2/3p4/8/5NP1/PPPPPPBP/RNBQK2R w KQkq -",
+];
+
+/// Games that get this long are adjudicated as draws.
+pub const DEFAULT_MAX_PLIES: u32 = 400;
+
+/// One side of a match.
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub name: String,
+    pub config: EngineConfig,
+    pub limits: SearchLimits,
+}
+
+/// How a match is played.
+#[derive(Debug, Clone)]
+pub struct MatchSettings {
+    /// Every opening is played twice, once with each player as White.
+    pub openings: Vec<Board>,
+    /// Number of games, at most. Cycles through the openings if there are more games
+    /// than two per opening.
+    pub games: usize,
+    /// Games played at the same time, each with its own pair of engines.
+    pub concurrency: usize,
+    pub max_plies: u32,
+    /// Stop as soon as the test has an answer. Without one all games are played.
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        let openings: Vec<Board> = DEFAULT_OPENINGS
+            .iter()
+            .map(|fen| Board::from_str(fen).expect("invalid default opening"))
+            .collect();
+        MatchSettings {
+            games: openings.len() * 2,
+            openings,
+            concurrency: 1,
+            max_plies: DEFAULT_MAX_PLIES,
+            sprt: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl Outcome {
+    /// As in PGN: "1-0", "0-1" or "1/2-1/2".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::WhiteWins => "1-0",
+            Outcome::BlackWins => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+}
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    /// The same position for the third time.
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    /// Longer than [`MatchSettings::max_plies`].
+    MaxLength,
+}
+
+impl Termination {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "threefold repetition",
+            Termination::FiftyMoves => "fifty-move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::MaxLength => "maximum length",
+        }
+    }
+}
+
+/// A finished game.
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    /// Number of the game in the match, from 0.
+    pub index: usize,
+    pub opening: Board,
+    /// Whether the first player had White.
+    pub first_is_white: bool,
+    pub moves: Vec<ChessMove>,
+    pub outcome: Outcome,
+    pub termination: Termination,
+}
+
+impl GameRecord {
+    /// The result from the first player's point of view: 1, 0.5 or 0.
+    pub fn first_player_score(&self) -> f64 {
+        match self.outcome {
+            Outcome::Draw => 0.5,
+            Outcome::WhiteWins if self.first_is_white => 1.0,
+            Outcome::BlackWins if !self.first_is_white => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Wins, draws and losses of the first player.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn add(&mut self, game: &GameRecord) {
+        match game.first_player_score() {
+            score if score > 0.75 => self.wins += 1,
+            score if score < 0.25 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    /// Points per game, between 0 and 1.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    /// Variance of the score of a single game.
+    fn variance(&self) -> f64 {
+        let n = self.games().max(1) as f64;
+        let score = self.score();
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n
+    }
+
+    /// Elo difference of the first player over the second, and the margin of its
+    /// 95% confidence interval. Infinite while one side has all the points.
+    pub fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let stderr = (self.variance() / self.games().max(1) as f64).sqrt();
+        let low = score_to_elo(score - 1.96 * stderr);
+        let high = score_to_elo(score + 1.96 * stderr);
+        let margin = (high - low) / 2.0;
+        (
+            score_to_elo(score),
+            if margin.is_nan() {
+                f64::INFINITY
+            } else {
+                margin
+            },
+        )
+    }
+}
+
+/// Elo difference that gives this expected score.
+fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Expected score at this Elo difference.
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// A sequential probability ratio test of "the first player is `elo0` stronger"
+/// against "it is `elo1` stronger", with false positive rate `alpha` and false
+/// negative rate `beta`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    /// The usual bounds for testing a change that should gain a few Elo.
+    fn default() -> Self {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    /// Accept `elo1`: the change is an improvement.
+    H1,
+    /// Accept `elo0`: it isn't.
+    H0,
+    Continue,
+}
+
+impl Sprt {
+    /// The log-likelihood ratio below which H0 is accepted and above which H1 is.
+    pub fn bounds(&self) -> (f64, f64) {
+        let lower = (self.beta / (1.0 - self.alpha)).ln();
+        let upper = ((1.0 - self.beta) / self.alpha).ln();
+        (lower, upper)
+    }
+
+    /// Log-likelihood ratio of H1 over H0 given `score`, using the normal
+    /// approximation of the game results.
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        let variance = score.variance();
+        if score.games() == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        (s1 - s0) * (2.0 * score.score() - s0 - s1) * score.games() as f64 / (2.0 * variance)
+    }
+
+    pub fn result(&self, score: &MatchScore) -> SprtResult {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtResult::H1
+        } else if llr <= lower {
+            SprtResult::H0
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
+
+/// Play one game from `opening`, with fresh hash tables on both sides.
+pub fn play_game(
+    white: (&Engine, &SearchLimits),
+    black: (&Engine, &SearchLimits),
+    opening: &Board,
+    max_plies: u32,
+) -> (Vec<ChessMove>, Outcome, Termination) {
+    white.0.new_game();
+    black.0.new_game();
+    let stop = AtomicBool::new(false);
+    let mut board = *opening;
+    let mut history = GameHistory::default();
+    let mut moves = Vec::new();
+
+    loop {
+        match board.status() {
+            BoardStatus::Checkmate => {
+                let outcome = match board.side_to_move() {
+                    Color::White => Outcome::BlackWins,
+                    Color::Black => Outcome::WhiteWins,
+                };
+                return (moves, outcome, Termination::Checkmate);
+            }
+            BoardStatus::Stalemate => return (moves, Outcome::Draw, Termination::Stalemate),
+            BoardStatus::Ongoing => {}
+        }
+        let termination = if history.halfmove_clock >= 100 {
+            Some(Termination::FiftyMoves)
+        } else if is_threefold(&board, &history) {
+            Some(Termination::Repetition)
+        } else if is_insufficient_material(&board) {
+            Some(Termination::InsufficientMaterial)
+        } else if moves.len() as u32 >= max_plies {
+            Some(Termination::MaxLength)
+        } else {
+            None
+        };
+        if let Some(termination) = termination {
+            return (moves, Outcome::Draw, termination);
+        }
+
+        let (engine, limits) = match board.side_to_move() {
+            Color::White => white,
+            Color::Black => black,
+        };
+        let info = engine
+            .search(&board, &history, limits, &stop, |_| {})
+            .expect("the game is still going, so there is a legal move");
+        history.push(&board, info.best_move);
+        board = board.make_move_new(info.best_move);
+        moves.push(info.best_move);
+    }
+}
+
+/// Has the current position been on the board twice before?
+fn is_threefold(board: &Board, history: &GameHistory) -> bool {
+    let hash = board.get_hash();
+    let earlier = history
+        .hashes
+        .iter()
+        .rev()
+        .take(history.halfmove_clock as usize);
+    earlier.filter(|&&h| h == hash).count() >= 2
+}
+
+/// Neither side can possibly mate: bare kings, a single minor piece, or only
+/// bishops that are all on the same color of squares.
+fn is_insufficient_material(board: &Board) -> bool {
+    let heavy =
+        *board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+    if heavy.popcnt() > 0 {
+        return false;
+    }
+    let knights = board.pieces(Piece::Knight).popcnt();
+    let bishops = *board.pieces(Piece::Bishop);
+    if knights + bishops.popcnt() <= 1 {
+        return true;
+    }
+    let dark_squares = chess::BitBoard(0xAA55_AA55_AA55_AA55);
+    knights == 0
+        && ((bishops & dark_squares).popcnt() == 0 || (bishops & !dark_squares).popcnt() == 0)
+}
+
+/// Play a match of `first` against `second`. `on_game` is called with every finished
+/// game (in the order they finish) and the score so far. Returns the final score.
+pub fn run_match(
+    first: &Player,
+    second: &Player,
+    settings: &MatchSettings,
+    mut on_game: impl FnMut(&GameRecord, &MatchScore),
+) -> MatchScore {
+    let mut score = MatchScore::default();
+    if settings.openings.is_empty() {
+        return score;
+    }
+    let next_game = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            let sender = sender.clone();
+            let (next_game, done) = (&next_game, &done);
+            scope.spawn(move || {
+                let first_engine = Engine::new(first.config.clone());
+                let second_engine = Engine::new(second.config.clone());
+                loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= settings.games || done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // Both colors of an opening one after the other, so a match cut
+                    // short by the SPRT is still mostly balanced.
+                    let opening = settings.openings[index / 2 % settings.openings.len()];
+                    let first_is_white = index % 2 == 0;
+                    let first_side = (&first_engine, &first.limits);
+                    let second_side = (&second_engine, &second.limits);
+                    let (white, black) = match first_is_white {
+                        true => (first_side, second_side),
+                        false => (second_side, first_side),
+                    };
+                    let (moves, outcome, termination) =
+                        play_game(white, black, &opening, settings.max_plies);
+                    let record = GameRecord {
+                        index,
+                        opening,
+                        first_is_white,
+                        moves,
+                        outcome,
+                        termination,
+                    };
+                    if sender.send(record).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Otherwise the receiver below would wait for this one forever.
+        drop(sender);
+
+        for record in receiver {
+            score.add(&record);
+            on_game(&record, &score);
+            let decided = settings
+                .sprt
+                .is_some_and(|sprt| sprt.result(&score) != SprtResult::Continue);
+            if decided {
+                done.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    score
+}
@@ -0,0 +1,1251 @@
+//! Syzygy endgame tablebase probing (`.rtbw` WDL and `.rtbz` DTZ files).
+//!
+//! This follows the reference prober by Ronald de Man as it lives on in Stockfish:
+//! positions are mapped to an index the same way the generator did, and the value at
+//! that index is decompressed from the table's Huffman coded blocks.
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, ALL_SQUARES};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, OnceLock};
+
+// The biggest tables that exist.
+const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Per-table flags in the file.
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Piece letters in the order they appear in table names.
+const PIECE_ORDER: [(char, Piece); 6] = [
+    ('K', Piece::King),
+    ('Q', Piece::Queen),
+    ('R', Piece::Rook),
+    ('B', Piece::Bishop),
+    ('N', Piece::Knight),
+    ('P', Piece::Pawn),
+];
+
+/// Win/draw/loss for the side to move. Cursed wins and blessed losses are wins and
+/// losses that the fifty-move rule turns into draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn value(self) -> i32 {
+        match self {
+            Wdl::Loss => -2,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 1,
+            Wdl::Win => 2,
+        }
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-self.value())
+    }
+}
+
+/// The tablebase verdict on a root position, see [`Tablebases::probe_root`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootProbe {
+    /// The moves that keep the best result: the fastest wins, every draw, or the
+    /// slowest losses.
+    pub moves: Vec<ChessMove>,
+    /// The result, counting the fifty-move rule from the current halfmove clock.
+    pub wdl: Wdl,
+    /// Plies until the next capture or pawn move when playing `moves`, negative when
+    /// losing and 0 for draws.
+    pub dtz: i32,
+}
+
+/// A set of Syzygy tables found on disk. Table files are only mapped into memory
+/// the first time a position needs them.
+pub struct Tablebases {
+    // Every table under both of its names, e.g. "KRvK" and "KvKR".
+    tables: HashMap<String, usize>,
+    entries: Vec<TableEntry>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Find the tables in `path`, which may list several directories separated like
+    /// the `PATH` variable (`:` on Unix, `;` on Windows).
+    pub fn open(path: impl AsRef<std::ffi::OsStr>) -> io::Result<Self> {
+        let mut tablebases = Tablebases {
+            tables: HashMap::new(),
+            entries: Vec::new(),
+            max_pieces: 0,
+        };
+        for dir in std::env::split_paths(&path) {
+            for file in fs::read_dir(&dir)? {
+                let file_path = file?.path();
+                if file_path.extension().is_some_and(|ext| ext == "rtbw") {
+                    tablebases.add(&dir, &file_path);
+                }
+            }
+        }
+        Ok(tablebases)
+    }
+
+    fn add(&mut self, dir: &Path, wdl_path: &Path) {
+        let Some(name) = wdl_path.file_stem().and_then(|stem| stem.to_str()) else {
+            return;
+        };
+        let Some(material) = Material::parse(name) else {
+            return;
+        };
+        if self.tables.contains_key(name) {
+            return;
+        }
+        self.max_pieces = self.max_pieces.max(material.piece_count);
+        let index = self.entries.len();
+        self.tables.insert(material.mirrored_name(), index);
+        self.tables.insert(name.to_string(), index);
+        self.entries.push(TableEntry {
+            wdl_path: wdl_path.to_path_buf(),
+            dtz_path: dir.join(format!("{name}.rtbz")),
+            material,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        });
+    }
+
+    /// Number of tables (WDL files) found.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Pieces (kings included) in the biggest table, 0 without tables.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Can `board` be looked up at all? Tables have no castling rights.
+    fn covers(&self, board: &Board) -> bool {
+        board.combined().popcnt() as usize <= self.max_pieces
+            && [Color::White, Color::Black]
+                .into_iter()
+                .all(|color| board.castle_rights(color) == chess::CastleRights::NoRights)
+    }
+
+    /// Win/draw/loss for the side to move, assuming the halfmove clock was just
+    /// reset. `None` if the position is not in the tables.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Distance to zeroing (the next capture or pawn move) in plies, positive when the
+    /// side to move wins and negative when it loses. 0 means a draw. Like
+    /// [`Tablebases::probe_wdl`] this assumes the halfmove clock was just reset; wins
+    /// and losses that are spoiled by the fifty-move rule are 100 plies further away.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.dtz(board)
+    }
+
+    /// The best moves at the root, with `halfmove_clock` plies already played towards
+    /// the fifty-move rule. `None` if any move leads out of the tables (or there are
+    /// no legal moves).
+    pub fn probe_root(&self, board: &Board, halfmove_clock: u32) -> Option<RootProbe> {
+        if !self.covers(board) || board.status() != BoardStatus::Ongoing {
+            return None;
+        }
+        let clock = halfmove_clock as i32;
+        let mut ranked = Vec::new();
+
+        for mv in MoveGen::new_legal(board) {
+            let child = board.make_move_new(mv);
+            let mut dtz = if is_zeroing(board, mv) {
+                dtz_before_zeroing(-self.search(&child, false)?.0)
+            } else {
+                let dtz = -self.dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            // A mate is as quick a win as there is.
+            if dtz == 2 && child.status() == BoardStatus::Checkmate {
+                dtz = 1;
+            }
+            ranked.push((mv, dtz, root_rank(dtz, clock)));
+        }
+
+        let best = ranked.iter().map(|&(_, _, rank)| rank).max()?;
+        let dtz = ranked.iter().find(|&&(_, _, rank)| rank == best).map(|&(_, dtz, _)| dtz)?;
+        let wdl = match best {
+            rank if rank > 2 * MAX_DTZ => Wdl::Win,
+            rank if rank > 0 => Wdl::CursedWin,
+            0 => Wdl::Draw,
+            rank if rank > -2 * MAX_DTZ => Wdl::BlessedLoss,
+            _ => Wdl::Loss,
+        };
+        let moves = ranked
+            .into_iter()
+            .filter(|&(_, _, rank)| rank == best)
+            .map(|(mv, _, _)| mv)
+            .collect();
+        Some(RootProbe { moves, wdl, dtz })
+    }
+
+    /// Look `board` up, resolving captures first.
+    ///
+    /// The generator stores "don't care" values for positions where a capture is at
+    /// least as good as anything else, to compress better, so the captures (and with
+    /// `check_zeroing` also the pawn moves) have to be tried before trusting the table.
+    /// The flag in the result is set when the best move is one of those, in which case
+    /// the DTZ table can't be trusted either.
+    fn search(&self, board: &Board, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for &mv in &moves {
+            let wanted = match check_zeroing {
+                true => is_zeroing(board, mv),
+                false => is_capture(board, mv),
+            };
+            if !wanted {
+                continue;
+            }
+            searched += 1;
+            let (value, _) = self.search(&board.make_move_new(mv), false)?;
+            let value = -value;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // Every legal move was a capture, so there's nothing left the table could add
+        // (and it may well be wrong, e.g. when the only captures are en passant).
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves { best } else { self.probe_wdl_table(board)? };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || no_more_moves));
+        }
+        Some((value, false))
+    }
+
+    fn dtz(&self, board: &Board) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        if let Some(dtz) = self.probe_dtz_table(board, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.value().signum());
+        }
+
+        // The DTZ table only has the other side to move, so go one ply deeper and take
+        // the best move.
+        let mut best: Option<i32> = None;
+        for mv in MoveGen::new_legal(board) {
+            let child = board.make_move_new(mv);
+            let zeroing = is_zeroing(board, mv);
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.dtz(&child)?
+            };
+            if dtz == 1 && child.status() == BoardStatus::Checkmate {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == wdl.value().signum() && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // No legal moves: we are mated.
+        Some(best.unwrap_or(-1))
+    }
+
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        if board.combined().popcnt() == 2 {
+            return Some(Wdl::Draw);
+        }
+        let (entry, name) = self.entry(board)?;
+        let table = entry.wdl.get_or_init(|| Table::open(&entry.wdl_path, &entry.material, TableKind::Wdl)).as_ref()?;
+        match table.probe(board, &entry.material, &name, Wdl::Draw) {
+            Probe::Value(value) => Some(Wdl::from_value(value)),
+            Probe::WrongSide => None,
+        }
+    }
+
+    /// `None` if the table is missing, `Some(None)` if it only has the other side to move.
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Option<Option<i32>> {
+        let (entry, name) = self.entry(board)?;
+        let table = entry.dtz.get_or_init(|| Table::open(&entry.dtz_path, &entry.material, TableKind::Dtz)).as_ref()?;
+        match table.probe(board, &entry.material, &name, wdl) {
+            Probe::Value(value) => Some(Some(value)),
+            Probe::WrongSide => Some(None),
+        }
+    }
+
+    fn entry(&self, board: &Board) -> Option<(&TableEntry, String)> {
+        let name = format!("{}v{}", side_name(board, Color::White), side_name(board, Color::Black));
+        let &index = self.tables.get(&name)?;
+        Some((&self.entries[index], name))
+    }
+}
+
+// Bigger than any distance to zeroing, for ranking root moves.
+const MAX_DTZ: i32 = 1 << 18;
+
+/// Rank a root move by its distance to zeroing: wins that beat the fifty-move rule,
+/// then the ones that don't, draws, losses the rule saves us from and real losses.
+/// Quicker wins and slower losses rank higher. Like Stockfish, a win only counts as
+/// safe with a ply to spare, the zeroing move has to come before the clock reaches 100.
+fn root_rank(dtz: i32, clock: i32) -> i32 {
+    match dtz {
+        dtz if dtz > 0 && dtz + clock <= 99 => 3 * MAX_DTZ - dtz,
+        dtz if dtz > 0 => 2 * MAX_DTZ - dtz,
+        0 => 0,
+        dtz if -dtz + clock > 100 => -2 * MAX_DTZ - dtz,
+        dtz => -3 * MAX_DTZ - dtz,
+    }
+}
+
+/// DTZ tables skip positions whose best move resets the halfmove clock, but those
+/// are always one ply from zeroing.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn is_capture(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_dest()).is_some()
+        // en passant
+        || (board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+            && mv.get_source().get_file() != mv.get_dest().get_file())
+}
+
+fn is_zeroing(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_source()) == Some(Piece::Pawn) || is_capture(board, mv)
+}
+
+/// e.g. "KRP" for a king, a rook and a pawn.
+fn side_name(board: &Board, color: Color) -> String {
+    let mut name = String::new();
+    for (letter, piece) in PIECE_ORDER {
+        let count = (board.pieces(piece) & board.color_combined(color)).popcnt();
+        name.extend(std::iter::repeat_n(letter, count as usize));
+    }
+    name
+}
+
+/// Piece code as used in the files: 1..6 for white pawn to king, 9..14 for black.
+fn piece_code(board: &Board, sq: chess::Square) -> u8 {
+    let piece = board.piece_on(sq).unwrap();
+    let color = board.color_on(sq).unwrap();
+    let kind = match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    };
+    if color == Color::Black { kind + 8 } else { kind }
+}
+
+/// What a table holds, worked out from its name.
+struct Material {
+    // e.g. "KRvK", with the stronger side first
+    name: String,
+    piece_count: usize,
+    has_pawns: bool,
+    // Some side has exactly one piece (or pawn) of some kind besides its king.
+    has_unique_pieces: bool,
+    // Pawns of the leading color (the one with fewer pawns) and of the other color.
+    pawn_count: [usize; 2],
+}
+
+impl Material {
+    fn parse(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let valid = |side: &str| {
+            side.starts_with('K')
+                && side[1..].chars().all(|c| "QRBNP".contains(c))
+        };
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+
+        let count = |side: &str, letter: char| side.chars().filter(|&c| c == letter).count();
+        let has_unique_pieces = [white, black]
+            .into_iter()
+            .any(|side| "QRBNP".chars().any(|letter| count(side, letter) == 1));
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        // The side with fewer pawns leads, it compresses better.
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+
+        Some(Material {
+            name: name.to_string(),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] },
+        })
+    }
+
+    fn mirrored_name(&self) -> String {
+        let (white, black) = self.name.split_once('v').unwrap();
+        format!("{black}v{white}")
+    }
+
+    /// Both sides have the same pieces, e.g. KRvKR.
+    fn is_symmetric(&self) -> bool {
+        self.mirrored_name() == self.name
+    }
+}
+
+struct TableEntry {
+    wdl_path: PathBuf,
+    dtz_path: PathBuf,
+    material: Material,
+    // Mapped on first use, `None` if the file is missing or broken.
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+enum Probe {
+    Value(i32),
+    // DTZ tables only store one side to move.
+    WrongSide,
+}
+
+/// One decompression table, there is one per side to move and (with pawns) per file
+/// of the leading pawn. Fields ending in `_at` are offsets into the file.
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: u64,
+    span: u64,
+    num_blocks: u64,
+    min_sym_len: u8,
+    lowest_sym_at: usize,
+    // base64[l - min_sym_len] is the lowest symbol of length l, left aligned.
+    base64: Vec<u64>,
+    // How many values (minus one) each symbol stands for.
+    symlen: Vec<u8>,
+    btree_at: usize,
+    block_length_at: usize,
+    block_length_size: u64,
+    sparse_index_at: usize,
+    sparse_index_size: u64,
+    data_at: usize,
+    // The pieces in the order they are encoded.
+    pieces: [u8; MAX_PIECES],
+    // Pieces that are encoded together, and the factor of each group in the index.
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    // Where the value maps for wins, losses, cursed wins and blessed losses start (DTZ only).
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    mmap: Mmap,
+    kind: TableKind,
+    // Indexed by side to move, then by file of the leading pawn.
+    pairs: Vec<Vec<PairsData>>,
+    // Start of the DTZ value maps.
+    map_at: usize,
+}
+
+impl Table {
+    fn open(path: &Path, material: &Material, kind: TableKind) -> Option<Table> {
+        let file = File::open(path).ok()?;
+        // Safety: tablebase files are never written to while we run.
+        let mmap = unsafe { Mmap::map(&file) }.ok()?;
+        let magic = if kind == TableKind::Wdl { WDL_MAGIC } else { DTZ_MAGIC };
+        if mmap.get(..4)? != magic {
+            return None;
+        }
+        let mut table = Table { mmap, kind, pairs: Vec::new(), map_at: 0 };
+        table.parse(material)?;
+        Some(table)
+    }
+
+    fn byte(&self, at: usize) -> Option<u8> {
+        self.mmap.get(at).copied()
+    }
+
+    fn u16_le(&self, at: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(self.mmap.get(at..at + 2)?.try_into().unwrap()))
+    }
+
+    fn u32_le(&self, at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(self.mmap.get(at..at + 4)?.try_into().unwrap()))
+    }
+
+    /// Read the header: piece orders, then the sizes of every decompression table,
+    /// the DTZ maps, sparse indices, block lengths and finally the compressed data.
+    fn parse(&mut self, material: &Material) -> Option<()> {
+        let flags = self.byte(4)?;
+        if (flags & 2 != 0) != material.has_pawns || (flags & 1 != 0) == material.is_symmetric() {
+            return None;
+        }
+        let mut at = 5;
+
+        let sides = if self.kind == TableKind::Wdl && !material.is_symmetric() { 2 } else { 1 };
+        let files = if material.has_pawns { 4 } else { 1 };
+        // Pawns on both sides
+        let pp = material.has_pawns && material.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let first = self.byte(at)?;
+            let second = if pp { self.byte(at + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            at += 1 + pp as usize;
+
+            for k in 0..material.piece_count {
+                let byte = self.byte(at)?;
+                for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                    side_pairs[file].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                at += 1;
+            }
+            for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                set_groups(material, &mut side_pairs[file], order[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                at = self.set_sizes(&mut side_pairs[file], at)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            self.map_at = at;
+            for d in pairs[0].iter_mut() {
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for map_idx in d.map_idx.iter_mut() {
+                        *map_idx = (at - self.map_at) / 2 + 1;
+                        at += 2 * self.u16_le(at)? as usize + 2;
+                    }
+                } else {
+                    for map_idx in d.map_idx.iter_mut() {
+                        *map_idx = at - self.map_at + 1;
+                        at += self.byte(at)? as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                side_pairs[file].sparse_index_at = at;
+                at += side_pairs[file].sparse_index_size as usize * 6;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                side_pairs[file].block_length_at = at;
+                at += side_pairs[file].block_length_size as usize * 2;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                at = (at + 0x3F) & !0x3F;
+                side_pairs[file].data_at = at;
+                at += (side_pairs[file].num_blocks * side_pairs[file].block_size) as usize;
+            }
+        }
+        if at > self.mmap.len() {
+            return None;
+        }
+
+        self.pairs = pairs;
+        Some(())
+    }
+
+    /// Read the sizes and the Huffman code of one decompression table.
+    fn set_sizes(&self, d: &mut PairsData, mut at: usize) -> Option<usize> {
+        d.flags = self.byte(at)?;
+        at += 1;
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            // The whole table is this one value.
+            d.min_sym_len = self.byte(at)?;
+            return Some(at + 1);
+        }
+
+        // The last group factor is the number of positions in the table.
+        let groups = d.group_len.iter().position(|&len| len == 0).unwrap();
+        let tb_size = d.group_idx[groups];
+
+        d.block_size = 1 << self.byte(at)?;
+        d.span = 1 << self.byte(at + 1)?;
+        d.sparse_index_size = tb_size.div_ceil(d.span);
+        let padding = self.byte(at + 2)? as u64;
+        d.num_blocks = self.u32_le(at + 3)? as u64;
+        // Padded so the sparse index never points past the end.
+        d.block_length_size = d.num_blocks + padding;
+        let max_sym_len = self.byte(at + 7)?;
+        d.min_sym_len = self.byte(at + 8)?;
+        at += 9;
+        if max_sym_len < d.min_sym_len || d.min_sym_len == 0 {
+            return None;
+        }
+        d.lowest_sym_at = at;
+
+        // A canonical Huffman code: longer codes have lower values, so
+        // lowest_sym[l] >= lowest_sym[l + 1]. base64 holds the lowest code of every
+        // length padded to 64 bits, so that base64[l] >= base64[l + 1] as well.
+        let lengths = (max_sym_len - d.min_sym_len) as usize + 1;
+        d.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = self.u16_le(at + 2 * i)? as u64;
+            let next_lowest = self.u16_le(at + 2 * (i + 1))? as u64;
+            d.base64[i] = (d.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in d.base64.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - i as u32 - d.min_sym_len as u32).unwrap_or(0);
+        }
+        at += lengths * 2;
+
+        let symbols = self.u16_le(at)? as usize;
+        at += 2;
+        d.btree_at = at;
+        if self.mmap.len() < at + 3 * symbols {
+            return None;
+        }
+
+        // Symbols stand for pairs of other symbols ("recursive pairing"), count how
+        // many values each one expands to.
+        d.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                d.symlen[sym] = self.set_symlen(d, sym, &mut visited)?;
+            }
+        }
+
+        Some(at + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_symlen(&self, d: &mut PairsData, sym: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[sym] = true;
+        let (left, right) = self.children(d.btree_at, sym);
+        if right == 0xFFF {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if *visited.get(child)? {
+                continue;
+            }
+            d.symlen[child] = self.set_symlen(d, child, visited)?;
+        }
+        Some((d.symlen[left] as u32 + d.symlen[right] as u32 + 1) as u8)
+    }
+
+    /// The two symbols `sym` expands to. For leaves the left one is the value.
+    fn children(&self, btree_at: usize, sym: usize) -> (usize, usize) {
+        let lr = &self.mmap[btree_at + 3 * sym..btree_at + 3 * sym + 3];
+        let left = (((lr[1] & 0xF) as usize) << 8) | lr[0] as usize;
+        let right = ((lr[2] as usize) << 4) | (lr[1] >> 4) as usize;
+        (left, right)
+    }
+
+    /// Decompress the value stored at `idx`.
+    fn decompress(&self, d: &PairsData, idx: u64) -> i32 {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return d.min_sym_len as i32;
+        }
+
+        // Sparse index entry k points at the value with index k * span + span / 2,
+        // walk the block lengths from there.
+        let k = (idx / d.span) as usize;
+        let entry = d.sparse_index_at + 6 * k;
+        let mut block = self.u32_le(entry).unwrap_or(0) as usize;
+        let mut offset = self.u16_le(entry + 4).unwrap_or(0) as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        let block_length = |block: usize| self.u16_le(d.block_length_at + 2 * block).unwrap_or(0) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        // Decode symbols from the start of the block until we reach our value.
+        let mut at = d.data_at + block * d.block_size as usize;
+        let read_u32 = |at: usize| {
+            self.mmap.get(at..at + 4).map_or(0, |b| u32::from_be_bytes(b.try_into().unwrap()))
+        };
+        let mut buf = ((read_u32(at) as u64) << 32) | read_u32(at + 4) as u64;
+        at += 8;
+        let mut buf_size = 64;
+        let min_sym_len = d.min_sym_len as usize;
+
+        let mut sym = loop {
+            let mut len = 0;
+            while buf < d.base64[len] {
+                len += 1;
+            }
+            let lowest = self.u16_le(d.lowest_sym_at + 2 * len).unwrap_or(0);
+            let sym = (((buf - d.base64[len]) >> (64 - len - min_sym_len)) as u16).wrapping_add(lowest) as usize;
+
+            let count = d.symlen[sym] as i64 + 1;
+            if offset < count {
+                break sym;
+            }
+            offset -= count;
+            let len = len + min_sym_len;
+            buf <<= len;
+            buf_size -= len;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32(at) as u64) << (64 - buf_size);
+                at += 4;
+            }
+        };
+
+        // Expand the symbol until we are down to the single value we want.
+        while d.symlen[sym] != 0 {
+            let (left, right) = self.children(d.btree_at, sym);
+            let left_count = d.symlen[left] as i64 + 1;
+            if offset < left_count {
+                sym = left;
+            } else {
+                offset -= left_count;
+                sym = right;
+            }
+        }
+        self.children(d.btree_at, sym).0 as i32
+    }
+
+    /// Turn a decompressed value into a WDL value (-2..2) or a DTZ in plies.
+    fn map_score(&self, d: &PairsData, value: i32, wdl: Wdl) -> i32 {
+        if self.kind == TableKind::Wdl {
+            return value - 2;
+        }
+
+        let mut value = value as usize;
+        if d.flags & FLAG_MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                _ => 3,
+            };
+            let index = d.map_idx[map] + value;
+            value = match d.flags & FLAG_WIDE != 0 {
+                true => self.u16_le(self.map_at + 2 * index).unwrap_or(0) as usize,
+                false => self.byte(self.map_at + index).unwrap_or(0) as usize,
+            };
+        }
+
+        // Some tables count moves instead of plies.
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+            _ => true,
+        };
+        let value = value as i32;
+        if in_moves { 2 * value + 1 } else { value + 1 }
+    }
+
+    /// Look up `board`, whose material is `name` and belongs to this table.
+    fn probe(&self, board: &Board, material: &Material, name: &str, wdl: Wdl) -> Probe {
+        let tables = &*INDEX_TABLES;
+        let black_to_move = board.side_to_move() == Color::Black;
+
+        // Tables are stored with the stronger side as White, and symmetric tables only
+        // with White to move, so otherwise swap the colors and flip the board.
+        let flip = (material.is_symmetric() && black_to_move) || name != material.name;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = [0u8; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0u64;
+        let mut lead_pawn_count = 0;
+        let mut tb_file = 0;
+
+        // With pawns there's a table for every file of the leading pawn, which is the
+        // one closest to the edge and, on the same file, the lowest one.
+        if material.has_pawns {
+            let lead_color = if (self.pairs[0][0].pieces[0] ^ flip_color) & 8 == 0 { Color::White } else { Color::Black };
+            lead_pawns = (board.pieces(Piece::Pawn) & board.color_combined(lead_color)).0;
+            let mut pawns = lead_pawns;
+            while pawns != 0 {
+                squares[size] = pawns.trailing_zeros() as u8 ^ flip_squares;
+                pawns &= pawns - 1;
+                size += 1;
+            }
+            lead_pawn_count = size;
+            let lead = (0..size).max_by_key(|&i| tables.map_pawns[squares[i] as usize]).unwrap();
+            squares.swap(0, lead);
+            let file = (squares[0] & 7) as usize;
+            tb_file = file.min(7 - file);
+        }
+
+        let d = &self.pairs[stm % self.pairs.len()][tb_file];
+        // Symmetric tables without pawns work for both sides after flipping.
+        let both_sides = material.is_symmetric() && !material.has_pawns;
+        if self.kind == TableKind::Dtz && (d.flags & FLAG_STM) as usize != stm && !both_sides {
+            return Probe::WrongSide;
+        }
+
+        let mut rest = board.combined().0 ^ lead_pawns;
+        while rest != 0 {
+            let sq = rest.trailing_zeros() as usize;
+            rest &= rest - 1;
+            squares[size] = sq as u8 ^ flip_squares;
+            pieces[size] = piece_code(board, ALL_SQUARES[sq]) ^ flip_color;
+            size += 1;
+        }
+
+        // Put the pieces in the order the table encodes them.
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so the leading piece is on files a-d.
+        if squares[0] & 7 > 3 {
+            for sq in squares[..size].iter_mut() {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if material.has_pawns {
+            idx = tables.lead_pawn_idx[lead_pawn_count][squares[0] as usize];
+            squares[1..lead_pawn_count].sort_by_key(|&sq| tables.map_pawns[sq as usize]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+                idx += tables.binomial[i][tables.map_pawns[sq as usize] as usize];
+            }
+        } else {
+            // Without pawns, mirror the leading piece into ranks 1-4 and then below
+            // the a1-h8 diagonal.
+            if squares[0] >> 3 > 3 {
+                for sq in squares[..size].iter_mut() {
+                    *sq ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => {
+                        for sq in squares[i..size].iter_mut() {
+                            *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                        }
+                    }
+                    _ => {}
+                }
+                break;
+            }
+            idx = if material.has_unique_pieces {
+                encode_unique(tables, &squares)
+            } else {
+                tables.map_kk[tables.map_a1d1d4[squares[0] as usize]][squares[1] as usize]
+            };
+        }
+
+        // Then every other group, each by the squares its pieces are on, not counting
+        // squares already taken by earlier groups.
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                let sq = sq as usize - adjust - if remaining_pawns { 8 } else { 0 };
+                n += tables.binomial[i + 1][sq];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Probe::Value(self.map_score(d, self.decompress(d, idx), wdl))
+    }
+}
+
+/// Index of the first three pieces when at least three of them are unique.
+fn encode_unique(tables: &IndexTables, squares: &[u8; MAX_PIECES]) -> u64 {
+    let [s0, s1, s2] = [squares[0] as u64, squares[1] as u64, squares[2] as u64];
+    let adjust1 = (s1 > s0) as u64;
+    let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+    let rank = |sq: u64| sq >> 3;
+
+    if off_diagonal(squares[0]) != 0 {
+        (tables.map_a1d1d4[s0 as usize] as u64 * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+    } else if off_diagonal(squares[1]) != 0 {
+        (6 * 63 + rank(s0) * 28 + tables.map_b1h1h7[s1 as usize]) * 62 + s2 - adjust2
+    } else if off_diagonal(squares[2]) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank(s0) * 7 * 28 + (rank(s1) - adjust1) * 28
+            + tables.map_b1h1h7[s2 as usize]
+    } else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(s0) * 7 * 6 + (rank(s1) - adjust1) * 6
+            + (rank(s2) - adjust2)
+    }
+}
+
+/// Rank minus file: 0 on the a1-h8 diagonal, negative below it.
+fn off_diagonal(sq: u8) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+/// Split the pieces into groups that are encoded together and work out the factor of
+/// every group in the index. `order` says in which order the leading group and the
+/// other side's pawns are encoded (0xF: not at all).
+fn set_groups(material: &Material, d: &mut PairsData, order: [u8; 2], file: usize) {
+    let tables = &*INDEX_TABLES;
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns { 0 } else if material.has_unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let pp = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if pp { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+    let mut idx: u64 = 1;
+
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if material.has_pawns {
+                tables.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= tables.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= tables.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+/// Lookup tables for turning positions into indices, the same for every table.
+struct IndexTables {
+    // binomial[k][n]: ways to choose k of n squares
+    binomial: [[u64; 64]; MAX_PIECES],
+    // Squares below the a1-h8 diagonal as 0..27
+    map_b1h1h7: [u64; 64],
+    // The a1-d1-d4 triangle as 0..9, diagonal squares last
+    map_a1d1d4: [usize; 64],
+    // The 462 ways to place two kings with the first in the a1-d1-d4 triangle
+    map_kk: [[u64; 64]; 10],
+    // a2-h7 as 0..47, the highest is the leading pawn
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static INDEX_TABLES: LazyLock<IndexTables> = LazyLock::new(IndexTables::new);
+
+impl IndexTables {
+    fn new() -> Self {
+        let mut tables = IndexTables {
+            binomial: [[0; 64]; MAX_PIECES],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for sq in 0..64u8 {
+            if off_diagonal(sq) < 0 {
+                tables.map_b1h1h7[sq as usize] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in 0..=27u8 {
+            if sq & 7 > 3 {
+                continue;
+            }
+            if off_diagonal(sq) < 0 {
+                tables.map_a1d1d4[sq as usize] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            tables.map_a1d1d4[sq as usize] = code;
+            code += 1;
+        }
+
+        // Kings next to each other are illegal, and with the first king on the
+        // diagonal the second one is mirrored below it. Both on the diagonal go last.
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            for s1 in 0..=27u8 {
+                if s1 & 7 > 3 || off_diagonal(s1) > 0 || tables.map_a1d1d4[s1 as usize] != idx {
+                    continue;
+                }
+                for s2 in 0..64u8 {
+                    let file_distance = ((s1 & 7) as i32 - (s2 & 7) as i32).abs();
+                    let rank_distance = ((s1 >> 3) as i32 - (s2 >> 3) as i32).abs();
+                    if file_distance <= 1 && rank_distance <= 1 {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        tables.map_kk[idx][s2 as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            tables.map_kk[idx][s2 as usize] = code;
+            code += 1;
+        }
+
+        tables.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                let with = if k > 0 { tables.binomial[k - 1][n - 1] } else { 0 };
+                let without = if k < n { tables.binomial[k][n - 1] } else { 0 };
+                tables.binomial[k][n] = with + without;
+            }
+        }
+
+        // The leading pawn is the one nearest the edge and then the lowest one, which
+        // leaves fewer squares for the others the further it is from a2.
+        let mut available = 48;
+        for lead_pawn_count in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = 8 * rank + file;
+                    if lead_pawn_count == 1 {
+                        available -= 2;
+                        tables.map_pawns[sq] = available + 1;
+                        tables.map_pawns[sq ^ 7] = available;
+                    }
+                    tables.lead_pawn_idx[lead_pawn_count][sq] = idx;
+                    idx += tables.binomial[lead_pawn_count - 1][tables.map_pawns[sq] as usize];
+                }
+                tables.lead_pawns_size[lead_pawn_count][file] = idx;
+            }
+        }
+
+        tables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_kings_have_462_placements() {
+        let tables = &*INDEX_TABLES;
+        let mut codes = Vec::new();
+        for s1 in 0..64u8 {
+            // The first king in the a1-d1-d4 triangle
+            if s1 & 7 > 3 || off_diagonal(s1) > 0 {
+                continue;
+            }
+            for s2 in 0..64u8 {
+                let adjacent = (s1 & 7).abs_diff(s2 & 7) <= 1 && (s1 >> 3).abs_diff(s2 >> 3) <= 1;
+                if adjacent || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                    continue;
+                }
+                codes.push(tables.map_kk[tables.map_a1d1d4[s1 as usize]][s2 as usize]);
+            }
+        }
+        codes.sort_unstable();
+        assert_eq!(codes, (0..462).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn index_tables() {
+        let tables = &*INDEX_TABLES;
+        assert_eq!(tables.binomial[0][10], 1);
+        assert_eq!(tables.binomial[2][10], 45);
+        assert_eq!(tables.binomial[3][48], 17296);
+        assert_eq!(tables.binomial[5][63], 7028847);
+        assert_eq!(tables.binomial[4][3], 0);
+
+        // The triangle is a1 b1 c1 d1 b2 c2 d2 c3 d3 d4, diagonal squares last
+        assert_eq!(tables.map_a1d1d4[1], 0);
+        assert_eq!(tables.map_a1d1d4[0], 6);
+        assert_eq!(tables.map_a1d1d4[27], 9);
+        assert_eq!((0..64).filter(|&sq| off_diagonal(sq) < 0).map(|sq| tables.map_b1h1h7[sq as usize]).max(), Some(27));
+
+        // One leading pawn can be on any of the six squares of its file; with two,
+        // the other one has to be on a square with a lower code.
+        assert_eq!(tables.lead_pawns_size[1], [6; 4]);
+        assert_eq!(tables.lead_pawns_size[2][0], 47 + 45 + 43 + 41 + 39 + 37);
+        // Every set of pawns has exactly one leading pawn
+        for count in 1..=5 {
+            let sets: u64 = (8..56).map(|sq| tables.binomial[count - 1][tables.map_pawns[sq] as usize]).sum();
+            assert_eq!(sets, tables.binomial[count][48], "{count} pawns");
+        }
+    }
+
+    #[test]
+    fn material_from_table_names() {
+        let krvk = Material::parse("KRvK").unwrap();
+        assert_eq!(krvk.piece_count, 3);
+        assert!(!krvk.has_pawns);
+        assert!(krvk.has_unique_pieces);
+        assert_eq!(krvk.mirrored_name(), "KvKR");
+        assert!(!krvk.is_symmetric());
+
+        let krrvk = Material::parse("KRRvK").unwrap();
+        assert!(!krrvk.has_unique_pieces);
+
+        // Black has fewer pawns, so it leads
+        let kppvkp = Material::parse("KPPvKP").unwrap();
+        assert!(kppvkp.has_pawns);
+        assert_eq!(kppvkp.pawn_count, [1, 2]);
+        assert_eq!(Material::parse("KPvKPP").unwrap().pawn_count, [1, 2]);
+        // Without black pawns white leads
+        assert_eq!(Material::parse("KPPvKR").unwrap().pawn_count, [2, 0]);
+
+        assert!(Material::parse("KRvKR").unwrap().is_symmetric());
+        assert_eq!(Material::parse("KQRvKN").unwrap().mirrored_name(), "KNvKQR");
+
+        for bad in ["KR", "RvK", "KXvK", "KRvR", "KQQQQvKQQ"] {
+            assert!(Material::parse(bad).is_none(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn root_moves_rank_by_result_then_distance() {
+        let order = [
+            (1, 0),
+            (5, 0),
+            (98, 1),
+            (99, 0),
+            // From here on the fifty-move rule may get in the way
+            (60, 40),
+            (99, 1),
+            (100, 0),
+            (0, 0),
+            (0, 99),
+            // Saved by the rule, and then real losses, the slowest first
+            (-101, 0),
+            (-60, 50),
+            (-60, 40),
+            (-5, 0),
+            (-1, 0),
+        ];
+        for pair in order.windows(2) {
+            let [(dtz1, clock1), (dtz2, clock2)] = pair else { unreachable!() };
+            assert!(root_rank(*dtz1, *clock1) >= root_rank(*dtz2, *clock2), "{pair:?}");
+        }
+        assert!(root_rank(99, 0) > root_rank(99, 1));
+        assert_eq!(root_rank(0, 0), root_rank(0, 99));
+        assert!(root_rank(-60, 50) > root_rank(-60, 40));
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+/// The side to move's clock, as sent by Lichess or a UCI `go` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    /// Moves until the next time control, if the game has one.
+    pub moves_to_go: Option<u32>,
+}
+
+/// How long a single move may take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    /// Don't start another iteration once this much time has passed. The search
+    /// may stretch it (up to `hard`) while the best move keeps changing.
+    pub soft: Duration,
+    /// Abort the search, even in the middle of an iteration, after this much time.
+    pub hard: Duration,
+}
+
+impl TimeBudget {
+    /// Exactly `time`, e.g. for UCI `go movetime`.
+    pub fn fixed(time: Duration) -> Self {
+        TimeBudget { soft: time, hard: time }
+    }
+}
+
+/// Splits the remaining clock time into per-move budgets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeManager {
+    /// Time lost per move to network/GUI lag, never spent on thinking.
+    pub move_overhead: Duration,
+}
+
+// Never plan for fewer moves than this when there is no `moves_to_go`,
+// so we keep a reserve for long endgames.
+const MIN_MOVES_LEFT: u32 = 20;
+// Expected game length in moves when the game has just started.
+const EXPECTED_GAME_LENGTH: u32 = 50;
+
+impl TimeManager {
+    pub fn new(move_overhead: Duration) -> Self {
+        TimeManager { move_overhead }
+    }
+
+    /// Budget for the next move, given our clock and how many moves (not plies)
+    /// each side has played so far.
+    pub fn allocate(&self, clock: &Clock, moves_played: u32) -> TimeBudget {
+        let usable = clock
+            .remaining
+            .saturating_sub(self.move_overhead)
+            .max(Duration::from_millis(1));
+
+        let moves_left = match clock.moves_to_go {
+            Some(n) => n.max(1),
+            None => EXPECTED_GAME_LENGTH
+                .saturating_sub(moves_played)
+                .max(MIN_MOVES_LEFT),
+        };
+
+        // Spend an even share of what is left plus most of the increment, and allow
+        // up to four times that when the search needs it, but never more than a
+        // quarter of the clock (or the whole share if this is the last move before
+        // the time control). The increment only arrives after the move, so however
+        // big it is one move never gets more than half the clock to start with, or
+        // three quarters in all.
+        let soft = (usable / moves_left + clock.increment * 3 / 4).min(usable / 2);
+        let hard_cap = if moves_left == 1 { usable } else { usable / 4 + clock.increment };
+        let hard = (soft * 4).min(hard_cap).min(usable * 3 / 4).max(soft);
+
+        TimeBudget { soft, hard }
+    }
+}
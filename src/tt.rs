@@ -0,0 +1,231 @@
+use chess::{ChessMove, Piece, ALL_SQUARES};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What the stored score tells us about the true value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact (searched with an open window).
+    Exact,
+    /// The search failed high, the true score is at least this.
+    Lower,
+    /// The search failed low, the true score is at most this.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<ChessMove>,
+}
+
+/// One slot of the table. The key is stored xor'ed with the data so that a
+/// torn write from two threads racing on the same slot is simply seen as a miss.
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Fixed-size transposition table keyed on `Board::get_hash()`.
+///
+/// It is lock free, so a single table can be shared by all the rayon workers
+/// searching the root moves in parallel.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    /// Create a table using roughly `size_mb` megabytes of memory.
+    pub fn new(size_mb: usize) -> Self {
+        let num_slots = (size_mb * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+        let slots = (0..num_slots)
+            .map(|_| Slot {
+                key: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            })
+            .collect();
+        TranspositionTable { slots }
+    }
+
+    /// Forget everything, e.g. when a new game starts.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        let idx = ((hash as u128 * self.slots.len() as u128) >> 64) as usize;
+        &self.slots[idx]
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        let slot = self.slot(hash);
+        let key = slot.key.load(Ordering::Relaxed);
+        let data = slot.data.load(Ordering::Relaxed);
+        if data == 0 || key ^ data != hash {
+            return None;
+        }
+        Some(unpack(data))
+    }
+
+    pub fn store(&self, hash: u64, depth: u32, score: i32, bound: Bound, best_move: Option<ChessMove>) {
+        let slot = self.slot(hash);
+        let old_key = slot.key.load(Ordering::Relaxed);
+        let old_data = slot.data.load(Ordering::Relaxed);
+
+        // Depth-preferred replacement: keep a deeper result for the same position,
+        // but always overwrite entries belonging to a different position.
+        if old_data != 0 && old_key ^ old_data == hash {
+            let old = unpack(old_data);
+            if depth < old.depth && bound != Bound::Exact {
+                return;
+            }
+        }
+
+        // Don't lose a known best move just because this search didn't find one.
+        let best_move = best_move.or_else(|| self.probe(hash).and_then(|e| e.best_move));
+
+        let data = pack(depth, score, bound, best_move);
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Layout of the data word:
+//   bits  0..16  move (from | to << 6 | promotion << 12), 0 means no move
+//   bits 16..48  score
+//   bits 48..56  depth
+//   bits 56..58  bound (0 is reserved so an entry is never all zeroes)
+fn pack(depth: u32, score: i32, bound: Bound, best_move: Option<ChessMove>) -> u64 {
+    let bound_bits: u64 = match bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    encode_move(best_move)
+        | ((score as u32 as u64) << 16)
+        | ((depth.min(255) as u64) << 48)
+        | (bound_bits << 56)
+}
+
+fn unpack(data: u64) -> TtEntry {
+    let bound = match (data >> 56) & 0b11 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    TtEntry {
+        depth: ((data >> 48) & 0xFF) as u32,
+        score: ((data >> 16) & 0xFFFF_FFFF) as u32 as i32,
+        bound,
+        best_move: decode_move((data & 0xFFFF) as u16),
+    }
+}
+
+fn encode_move(mv: Option<ChessMove>) -> u64 {
+    let Some(mv) = mv else { return 0 };
+    let promo = match mv.get_promotion() {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    mv.get_source().to_index() as u64 | (mv.get_dest().to_index() as u64) << 6 | promo << 12
+}
+
+fn decode_move(bits: u16) -> Option<ChessMove> {
+    if bits == 0 {
+        return None;
+    }
+    let from = ALL_SQUARES[(bits & 0x3F) as usize];
+    let to = ALL_SQUARES[((bits >> 6) & 0x3F) as usize];
+    let promo = match bits >> 12 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+    Some(ChessMove::new(from, to, promo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::Square;
+
+    fn entry(tt: &TranspositionTable, hash: u64) -> Option<(u32, i32, Bound, Option<ChessMove>)> {
+        tt.probe(hash).map(|e| (e.depth, e.score, e.bound, e.best_move))
+    }
+
+    #[test]
+    fn entries_survive_packing() {
+        let moves = [
+            None,
+            Some(ChessMove::new(Square::E2, Square::E4, None)),
+            Some(ChessMove::new(Square::H8, Square::A1, None)),
+            Some(ChessMove::new(Square::A7, Square::A8, Some(Piece::Queen))),
+            Some(ChessMove::new(Square::B2, Square::C1, Some(Piece::Knight))),
+            Some(ChessMove::new(Square::G7, Square::G8, Some(Piece::Bishop))),
+            Some(ChessMove::new(Square::H2, Square::H1, Some(Piece::Rook))),
+        ];
+        // Mate scores, negative ones and the extremes all fit in the 32 bits
+        let scores = [0, 1, -1, 35, -250, 31_990, -31_990, i32::MAX, i32::MIN];
+        for best_move in moves {
+            for score in scores {
+                for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+                    for depth in [0, 1, 64, 255] {
+                        let data = pack(depth, score, bound, best_move);
+                        assert_ne!(data, 0);
+                        let e = unpack(data);
+                        assert_eq!((e.depth, e.score, e.bound, e.best_move), (depth, score, bound, best_move));
+                    }
+                }
+            }
+        }
+        assert_eq!(unpack(pack(1000, 0, Bound::Exact, None)).depth, 255);
+    }
+
+    #[test]
+    fn other_positions_miss() {
+        // A single slot, so every position lands in it
+        let tt = TranspositionTable::new(0);
+        let mv = Some(ChessMove::new(Square::E2, Square::E4, None));
+        tt.store(0x1234, 5, 20, Bound::Exact, mv);
+        assert_eq!(entry(&tt, 0x1234), Some((5, 20, Bound::Exact, mv)));
+        assert_eq!(entry(&tt, 0x1235), None);
+
+        // Half of another position's entry, as a racing thread could leave it
+        let slot = tt.slot(0x1234);
+        slot.data.store(pack(6, 20, Bound::Exact, mv), Ordering::Relaxed);
+        assert_eq!(entry(&tt, 0x1234), None);
+
+        tt.store(0x1234, 5, 20, Bound::Exact, mv);
+        tt.clear();
+        assert_eq!(entry(&tt, 0x1234), None);
+    }
+
+    #[test]
+    fn deeper_results_are_kept() {
+        let tt = TranspositionTable::new(0);
+        let mv = Some(ChessMove::new(Square::G1, Square::F3, None));
+        tt.store(1, 8, 50, Bound::Lower, mv);
+
+        // A shallower bound tells us less
+        tt.store(1, 3, -10, Bound::Upper, None);
+        assert_eq!(entry(&tt, 1), Some((8, 50, Bound::Lower, mv)));
+
+        // but an exact score replaces it, keeping the best move when it has none
+        tt.store(1, 3, 10, Bound::Exact, None);
+        assert_eq!(entry(&tt, 1), Some((3, 10, Bound::Exact, mv)));
+
+        // and another position always does, however shallow
+        tt.store(2, 1, 0, Bound::Upper, None);
+        assert_eq!(entry(&tt, 2), Some((1, 0, Bound::Upper, None)));
+        assert_eq!(entry(&tt, 1), None);
+    }
+}
@@ -0,0 +1,39 @@
+//! The bench signature.
+use tinychess::bench::{run_bench, BENCH_POSITIONS};
+
+/// Nodes and best move of every bench position, and the total.
+fn bench(depth: u32) -> (Vec<(u64, String)>, u64) {
+    let mut positions = Vec::new();
+    let result = run_bench(depth, |i, info| {
+        assert_eq!(i, positions.len());
+        positions.push((info.nodes, tinychess::notation::format_move_as_uci(info.best_move)));
+    });
+    (positions, result.nodes)
+}
+
+#[test]
+fn the_signature_is_stable() {
+    // Depth 1 keeps a debug build quick, it is still a full search with quiescence
+    let (positions, nodes) = bench(1);
+    assert_eq!(positions.len(), BENCH_POSITIONS.len());
+    assert!(positions.iter().all(|&(nodes, _)| nodes > 0));
+    assert_eq!(positions.iter().map(|&(nodes, _)| nodes).sum::<u64>(), nodes);
+
+    // Any change to the search or the evaluation moves these: check the new
+    // numbers are what the change meant and update them in the same commit.
+    assert_eq!(nodes, 296275);
+    let best_moves: Vec<_> = positions.iter().map(|(_, best)| best.as_str()).collect();
+    assert_eq!(
+        best_moves,
+        [
+            "b1c3", "g8f6", "b1c3", "d5c4", "b8c6", "e1g1", "d2d4", "d7c8r", "c3d5", "f5e6",
+            "d4c6", "d3d4", "b4b2", "b5d6", "h5e5", "a1e1", "d8h4", "d1d3", "a3d6", "c1b1",
+            "e8e7", "d8e7", "f2f4", "d3c4", "g4g5", "h4h3", "b6d7", "c6c7", "a5a6", "e1e8",
+            "g3f3", "e4d6", "d3c3", "d6e5", "b3a4", "d1d5", "a2a4", "d2d4", "f2f4", "b4f4",
+            "c4c5", "g5f5", "e3e4", "h1h2", "b6b7", "b1c2", "f1e3", "b1c2", "e2d3", "e1f2",
+        ]
+    );
+
+    // Nothing carries over from one run to the next
+    assert_eq!(bench(1), (positions, nodes));
+}
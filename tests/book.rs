@@ -0,0 +1,85 @@
+//! Polyglot keys and book moves.
+use chess::{Board, ChessMove, Square};
+use std::str::FromStr;
+use tinychess::book::{polyglot_key, OpeningBook};
+use tinychess::notation::parse_san;
+
+/// The sample positions of the Polyglot book format description, each reached by
+/// playing the moves from the start.
+const SPEC_KEYS: [(&str, u64); 9] = [
+    ("", 0x463b96181691fc9c),
+    ("e4", 0x823c9b50fd114196),
+    ("e4 d5", 0x0756b94461c50fb0),
+    ("e4 d5 e5", 0x662fafb965db29d4),
+    // The first one where en passant is possible
+    ("e4 d5 e5 f5", 0x22a48b5a8e47ff78),
+    ("e4 d5 e5 f5 Ke2", 0x652a607ca3f242c1),
+    ("e4 d5 e5 f5 Ke2 Kf7", 0x00fdd303c946bdd9),
+    ("a4 b5 h4 b4 c4", 0x3c8123ea7b067637),
+    // White can only castle kingside
+    ("a4 b5 h4 b4 c4 bxc3 Ra3", 0x5c3f9b829b279560),
+];
+
+fn play(moves: &str) -> Board {
+    moves.split_whitespace().fold(Board::default(), |board, san| {
+        board.make_move_new(parse_san(&board, san).unwrap())
+    })
+}
+
+#[test]
+fn keys_match_the_spec() {
+    for (moves, key) in SPEC_KEYS {
+        assert_eq!(polyglot_key(&play(moves)), key, "after {moves:?}");
+    }
+}
+
+#[test]
+fn keys_from_fens_match_the_spec() {
+    // The FEN names e3 as the en passant square, but no black pawn can take
+    let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    assert_eq!(polyglot_key(&Board::from_str(fen).unwrap()), 0x823c9b50fd114196);
+    let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+    assert_eq!(polyglot_key(&Board::from_str(fen).unwrap()), 0x22a48b5a8e47ff78);
+}
+
+/// A book with one entry per move, all with weight 1.
+fn book_for(board: &Board, moves: &[u16]) -> OpeningBook {
+    let key = polyglot_key(board);
+    let mut bytes = Vec::new();
+    for &mv in moves {
+        bytes.extend(key.to_be_bytes());
+        bytes.extend(mv.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+    }
+    OpeningBook::from_bytes(&bytes).unwrap()
+}
+
+// Polyglot moves: to square in the low 6 bits, from square in the next 6
+fn polyglot_move(from: Square, to: Square) -> u16 {
+    ((from.to_index() as u16) << 6) | to.to_index() as u16
+}
+
+#[test]
+fn castling_is_stored_as_taking_the_rook() {
+    let board = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+    let book = book_for(&board, &[polyglot_move(Square::E1, Square::H1), polyglot_move(Square::E1, Square::A1)]);
+    let moves: Vec<ChessMove> = book.moves(&board).into_iter().map(|(mv, _)| mv).collect();
+    assert_eq!(
+        moves,
+        [ChessMove::new(Square::E1, Square::G1, None), ChessMove::new(Square::E1, Square::C1, None)]
+    );
+
+    let board = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq - 0 1").unwrap();
+    let book = book_for(&board, &[polyglot_move(Square::E8, Square::H8)]);
+    assert_eq!(book.choose_move(&board, 0.0), Some(ChessMove::new(Square::E8, Square::G8, None)));
+}
+
+#[test]
+fn illegal_and_foreign_moves_are_skipped() {
+    let board = Board::default();
+    // a1a3 would go through a pawn, and the entry is only found for its own position
+    let book = book_for(&board, &[polyglot_move(Square::E2, Square::E4), polyglot_move(Square::A1, Square::A3)]);
+    assert_eq!(book.moves(&board), [(ChessMove::new(Square::E2, Square::E4, None), 1)]);
+    assert!(book.moves(&play("e4")).is_empty());
+}
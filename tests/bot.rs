@@ -0,0 +1,400 @@
+//! The Lichess bot end to end, against a local stand-in for lichess.org.
+mod mock_lichess;
+
+use chess::{Board, MoveGen};
+use mock_lichess::{MockLichess, Recorded, ACCOUNT_ID};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+use tinychess::notation::{format_move_as_uci, parse_uci_move};
+use tinychess::pgn::PgnGame;
+
+/// The bot binary, killed when the test is done with it. What it says on stderr
+/// goes to a log, which is printed if the test fails.
+struct RunningBot {
+    process: Child,
+    dir: PathBuf,
+}
+
+impl RunningBot {
+    fn start(mock: &MockLichess, name: &str) -> Self {
+        Self::start_with_depth(mock, name, 3)
+    }
+
+    fn start_with_depth(mock: &MockLichess, name: &str, max_depth: u8) -> Self {
+        let dir = std::env::temp_dir().join(format!("tinychess-bot-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // JSON, since TOML can't turn the book and the tablebases off
+        let config = serde_json::json!({
+            "lichess": {"base_url": mock.url()},
+            "engine": {"hash_size_mb": 1, "threads": 1, "max_depth": max_depth, "move_overhead_ms": 50},
+            "book": {"path": null},
+            "syzygy": {"path": null},
+            "archive": {"dir": dir.join("games")},
+            "logging": {"level": "error"},
+        });
+        let config_path = dir.join("tinychess.json");
+        fs::write(&config_path, config.to_string()).unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_chess"))
+            .arg("--config")
+            .arg(&config_path)
+            .current_dir(&dir)
+            .env("LICHESS_BOT_TOKEN", "lip_test")
+            .stdout(Stdio::null())
+            .stderr(fs::File::create(dir.join("stderr.log")).unwrap())
+            .spawn()
+            .unwrap();
+        RunningBot { process, dir }
+    }
+
+    fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("stderr.log")).unwrap_or_default()
+    }
+
+    /// Wait until the log has a line with `text`.
+    fn wait_for_log(&self, text: &str) -> String {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let log = self.log();
+            if log.contains(text) {
+                return log;
+            }
+            assert!(Instant::now() < deadline, "the bot never said {text:?}");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// The CPU time the bot used so far, from /proc/<pid>/stat.
+    #[cfg(target_os = "linux")]
+    fn cpu_time(&self) -> Duration {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", self.process.id())).unwrap();
+        // The name in parentheses may have spaces, the fields after it don't. utime and
+        // stime are fields 14 and 15, in clock ticks of (nearly always) 1/100 s.
+        let fields: Vec<&str> = stat.rsplit_once(')').unwrap().1.split_whitespace().collect();
+        let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+        Duration::from_millis(ticks * 10)
+    }
+}
+
+impl Drop for RunningBot {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        if thread::panicking() {
+            eprintln!("--- bot log ---\n{}", self.log());
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn challenge(id: &str, variant: &str) -> String {
+    serde_json::json!({
+        "type": "challenge",
+        "challenge": {
+            "id": id,
+            "challenger": {"id": "bobby", "name": "Bobby", "rating": 1500},
+            "variant": {"key": variant},
+            "speed": "blitz",
+            "rated": true,
+            "timeControl": {"type": "clock", "limit": 300, "increment": 3},
+        },
+    })
+    .to_string()
+}
+
+fn game_start(id: &str) -> String {
+    serde_json::json!({"type": "gameStart", "game": {"id": id}}).to_string()
+}
+
+fn game_full(id: &str, white: &str, black: &str, moves: &str) -> String {
+    serde_json::json!({
+        "type": "gameFull",
+        "id": id,
+        "white": {"id": white.to_lowercase(), "name": white},
+        "black": {"id": black.to_lowercase(), "name": black},
+        "rated": false,
+        "speed": "blitz",
+        "clock": {"initial": 300000, "increment": 3000},
+        "state": game_state(moves, "started", None),
+    })
+    .to_string()
+}
+
+fn game_state(moves: &str, status: &str, winner: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "type": "gameState",
+        "moves": moves,
+        "wtime": 300000,
+        "btime": 300000,
+        "winc": 3000,
+        "binc": 3000,
+        "status": status,
+        "winner": winner,
+    })
+}
+
+/// The moves the bot sent in a game so far.
+fn moves_sent(mock: &MockLichess, game_id: &str) -> Vec<String> {
+    let prefix = format!("/api/bot/game/{game_id}/move/");
+    let requests = mock.requests();
+    requests.iter().filter_map(|r| r.path.strip_prefix(&prefix).map(str::to_string)).collect()
+}
+
+/// Wait until the bot sent `count` moves in a game, and return them.
+fn wait_for_moves(mock: &MockLichess, game_id: &str, count: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        let moves = moves_sent(mock, game_id);
+        if moves.len() >= count {
+            return moves;
+        }
+        assert!(Instant::now() < deadline, "only got moves {moves:?} in {game_id}");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn is_move_in(game_id: &str) -> impl Fn(&Recorded) -> bool + '_ {
+    move |r| r.method == "POST" && r.path.starts_with(&format!("/api/bot/game/{game_id}/move/"))
+}
+
+fn board_after(moves: &str) -> Board {
+    moves.split_whitespace().fold(Board::default(), |board, uci| {
+        board.make_move_new(parse_uci_move(&board, uci).unwrap())
+    })
+}
+
+#[test]
+fn challenges_are_accepted_or_declined() {
+    let mock = MockLichess::start();
+    let _bot = RunningBot::start(&mock, "challenges");
+
+    mock.send_event(&challenge("good", "standard"));
+    mock.send_event(&challenge("odd", "chess960"));
+
+    mock.wait_for("the standard challenge to be accepted", |r| {
+        r.method == "POST" && r.path == "/api/challenge/good/accept"
+    });
+    let declined = mock.wait_for("the chess960 challenge to be declined", |r| {
+        r.method == "POST" && r.path == "/api/challenge/odd/decline"
+    });
+    assert_eq!(declined.body, "reason=standard");
+    assert!(!mock.requests().iter().any(|r| r.path == "/api/challenge/odd/accept"));
+}
+
+#[test]
+fn the_bot_plays_its_own_color() {
+    let mock = MockLichess::start();
+    let _bot = RunningBot::start(&mock, "colors");
+
+    // White, and ids compared without caring about case
+    mock.send_game("white1", &game_full("white1", "TinyBot", "Bobby", ""));
+    mock.send_event(&game_start("white1"));
+    let first = mock.wait_for("a move as white", is_move_in("white1"));
+    let uci = first.path.rsplit('/').next().unwrap();
+    assert!(parse_uci_move(&Board::default(), uci).is_ok(), "{uci} isn't a first move for white");
+
+    // Black, so nothing happens until Bobby moves
+    mock.send_game("black1", &game_full("black1", "Bobby", ACCOUNT_ID, ""));
+    mock.send_event(&game_start("black1"));
+    thread::sleep(Duration::from_millis(500));
+    assert!(moves_sent(&mock, "black1").is_empty(), "moved for white");
+
+    mock.send_game("black1", &game_state("e2e4", "started", None).to_string());
+    let reply = mock.wait_for("a move as black", is_move_in("black1"));
+    let uci = reply.path.rsplit('/').next().unwrap();
+    assert!(parse_uci_move(&board_after("e2e4"), uci).is_ok(), "{uci} isn't a reply to 1. e4");
+    assert_eq!(moves_sent(&mock, "black1").len(), 1);
+}
+
+#[test]
+fn games_resume_after_a_disconnect_and_are_archived() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "archive");
+
+    mock.send_game("g1", &game_full("g1", "TinyBot", "Bobby", ""));
+    mock.send_event(&game_start("g1"));
+    let mut moves = wait_for_moves(&mock, "g1", 1)[0].clone();
+
+    // The connection drops, Bobby replies meanwhile, and the bot finds out from the
+    // gameFull of the next connection.
+    mock.disconnect_game("g1");
+    let board = board_after(&moves);
+    let reply = MoveGen::new_legal(&board).next().unwrap();
+    moves = format!("{moves} {}", format_move_as_uci(reply));
+    mock.send_game("g1", &game_full("g1", "TinyBot", "Bobby", &moves));
+    let ours = wait_for_moves(&mock, "g1", 2);
+    let streams = mock.requests().iter().filter(|r| r.path == "/api/bot/game/stream/g1").count();
+    assert_eq!(streams, 2);
+
+    moves = format!("{moves} {}", ours[1]);
+    mock.send_game("g1", &game_state(&moves, "resign", Some("white")).to_string());
+
+    let path = bot.dir.join("games").join("g1.pgn");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "the game was never archived");
+        thread::sleep(Duration::from_millis(50));
+    }
+    // Saved in one go, but give the write a moment to finish
+    thread::sleep(Duration::from_millis(100));
+    let pgn = fs::read_to_string(&path).unwrap();
+    assert!(pgn.contains("[Result \"1-0\"]"), "{pgn}");
+    assert!(pgn.contains("[Termination \"resign\"]"), "{pgn}");
+    assert!(pgn.contains(&format!("[Site \"{}/g1\"]", mock.url())), "{pgn}");
+    assert!(pgn.contains("[%eval"), "our moves should have their evals: {pgn}");
+}
+
+#[test]
+fn the_event_stream_reconnects_after_a_drop() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "events");
+    let is_event_stream = |r: &Recorded| r.path == "/api/stream/event";
+    mock.wait_for("the event stream", is_event_stream);
+
+    // Dropped, and the first try to reconnect fails too
+    mock.fail_next("/api/stream/event", 500);
+    mock.disconnect_events();
+    bot.wait_for_log("The event stream disconnected (closed by Lichess), reconnecting in");
+    // 1s of backoff at most before the failed try, 2s before the next one
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while mock.requests().iter().filter(|r| is_event_stream(r)).count() < 3 {
+        assert!(Instant::now() < deadline, "the bot never reconnected to the event stream");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // The bot still hears about challenges
+    mock.send_event(&challenge("later", "standard"));
+    mock.wait_for("the challenge after the reconnect to be accepted", |r| {
+        r.method == "POST" && r.path == "/api/challenge/later/accept"
+    });
+}
+
+#[test]
+fn games_from_a_position_start_there() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "position");
+
+    // Back rank mate in one, only on the board if the initial FEN is used
+    let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 30";
+    let mut game: serde_json::Value = serde_json::from_str(&game_full("fen1", "TinyBot", "Bobby", "")).unwrap();
+    game["initialFen"] = fen.into();
+    mock.send_game("fen1", &game.to_string());
+    mock.send_event(&game_start("fen1"));
+    assert_eq!(wait_for_moves(&mock, "fen1", 1), ["a1a8"]);
+
+    // The archive says where the game started, and reads back from there
+    mock.send_game("fen1", &game_state("a1a8", "mate", Some("white")).to_string());
+    let path = bot.dir.join("games").join("fen1.pgn");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "the game was never archived");
+        thread::sleep(Duration::from_millis(50));
+    }
+    thread::sleep(Duration::from_millis(100));
+    let pgn = fs::read_to_string(&path).unwrap();
+    assert!(pgn.contains("[SetUp \"1\"]"), "{pgn}");
+    assert!(pgn.contains(&format!("[FEN \"{fen}\"]")), "{pgn}");
+    assert!(pgn.contains("30. Ra8#"), "{pgn}");
+    let read = PgnGame::from_str(&pgn).unwrap();
+    assert_eq!(read.start, Board::from_str(fen).unwrap());
+    assert_eq!(read.moves.len(), 1);
+    assert_eq!(read.to_string(), pgn);
+}
+
+#[test]
+fn takebacks_replay_the_game() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "takeback");
+
+    mock.send_game("t1", &game_full("t1", "TinyBot", "Bobby", ""));
+    mock.send_event(&game_start("t1"));
+    let first = wait_for_moves(&mock, "t1", 1)[0].clone();
+    let reply = MoveGen::new_legal(&board_after(&first)).next().unwrap();
+    let moves = format!("{first} {}", format_move_as_uci(reply));
+    mock.send_game("t1", &game_state(&moves, "started", None).to_string());
+    let second = wait_for_moves(&mock, "t1", 2)[1].clone();
+    mock.send_game("t1", &game_state(&format!("{moves} {second}"), "started", None).to_string());
+
+    // Everything taken back, which isn't a continuation of what the bot knows
+    mock.send_game("t1", &game_state("", "started", None).to_string());
+    let third = wait_for_moves(&mock, "t1", 3)[2].clone();
+    assert!(parse_uci_move(&Board::default(), &third).is_ok(), "{third} isn't a first move for white");
+    let log = bot.log();
+    assert!(log.contains("Game t1: the 0 moves from Lichess don't continue our 3"), "{log}");
+}
+
+#[test]
+fn diverging_moves_replay_the_game_with_a_warning() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "diverge");
+
+    mock.send_game("d1", &game_full("d1", "TinyBot", "Bobby", ""));
+    mock.send_event(&game_start("d1"));
+    let first = wait_for_moves(&mock, "d1", 1)[0].clone();
+    let mut replies = MoveGen::new_legal(&board_after(&first)).map(format_move_as_uci);
+    let (reply, other_reply) = (replies.next().unwrap(), replies.next().unwrap());
+    let moves = format!("{first} {reply}");
+    mock.send_game("d1", &game_state(&moves, "started", None).to_string());
+    let second = wait_for_moves(&mock, "d1", 2)[1].clone();
+    mock.send_game("d1", &game_state(&format!("{moves} {second}"), "started", None).to_string());
+
+    // As long as the game, but Bobby's reply is another one
+    let other = format!("{first} {other_reply}");
+    mock.send_game("d1", &game_state(&other, "started", None).to_string());
+    let third = wait_for_moves(&mock, "d1", 3)[2].clone();
+    assert!(parse_uci_move(&board_after(&other), &third).is_ok(), "{third} isn't a move after {other}");
+    let log = bot.wait_for_log("out of sync");
+    assert!(log.contains("Game d1: the 2 moves from Lichess don't continue our 3"), "{log}");
+}
+
+#[test]
+fn unplayable_moves_end_the_game() {
+    let mock = MockLichess::start();
+    let bot = RunningBot::start(&mock, "unplayable");
+
+    mock.send_game("u1", &game_full("u1", "TinyBot", "Bobby", ""));
+    mock.send_event(&game_start("u1"));
+    let first = wait_for_moves(&mock, "u1", 1)[0].clone();
+
+    // Not a move at all, so not even replaying the game gets the bot back in sync
+    mock.send_game("u1", &game_state(&format!("{first} zz99"), "started", None).to_string());
+    let log = bot.wait_for_log("Error in game u1");
+    assert!(log.contains("out of sync"), "{log}");
+
+    // The bot gave up on the game rather than play on a board it can't trust
+    mock.send_game("u1", &game_state(&format!("{first} zz99 e2e4"), "started", None).to_string());
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(moves_sent(&mock, "u1"), [first]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn pondering_stops_when_a_game_ends_in_an_error() {
+    let mock = MockLichess::start();
+    // Deep enough that only the clock or a stop ends a search, and pondering has no clock
+    let bot = RunningBot::start_with_depth(&mock, "ponder", 64);
+
+    // A short clock, so the first move is quick
+    let mut game: serde_json::Value = serde_json::from_str(&game_full("p1", "TinyBot", "Bobby", "")).unwrap();
+    game["state"]["wtime"] = 2000.into();
+    game["state"]["winc"] = 0.into();
+    mock.send_game("p1", &game.to_string());
+    mock.send_event(&game_start("p1"));
+    let first = wait_for_moves(&mock, "p1", 1)[0].clone();
+
+    // Pondering on Bobby's time, until a reply that can't be played ends the game
+    thread::sleep(Duration::from_millis(300));
+    mock.send_game("p1", &game_state(&format!("{first} zz99"), "started", None).to_string());
+    let log = bot.wait_for_log("Error in game p1");
+    assert!(log.contains("out of sync"), "{log}");
+
+    // Give the search a moment to see the stop, then the bot should be idle
+    thread::sleep(Duration::from_millis(200));
+    let before = bot.cpu_time();
+    thread::sleep(Duration::from_secs(1));
+    let used = bot.cpu_time() - before;
+    assert!(used < Duration::from_millis(200), "still busy after the game ended, used {used:?} in 1s");
+}
@@ -0,0 +1,77 @@
+//! Accepting and declining challenges.
+use tinychess::challenge::{check_challenge, Challenge, DeclineReason, TimeControl};
+use tinychess::config::ChallengeRules;
+
+// Trimmed from a real challenge event.
+const CHALLENGE: &str = r#"{
+    "id": "H9fIRZUk",
+    "url": "https://lichess.org/H9fIRZUk",
+    "status": "created",
+    "challenger": {"id": "bobby", "name": "Bobby", "rating": 1635, "title": "BOT", "provisional": true, "online": true},
+    "destUser": {"id": "tinybot", "name": "tinybot", "rating": 1500, "title": "BOT"},
+    "variant": {"key": "standard", "name": "Standard", "short": "Std"},
+    "rated": true,
+    "speed": "rapid",
+    "timeControl": {"type": "clock", "limit": 600, "increment": 5, "show": "10+5"},
+    "color": "random",
+    "perf": {"name": "Rapid"}
+}"#;
+
+fn challenge() -> Challenge {
+    serde_json::from_str(CHALLENGE).unwrap()
+}
+
+#[test]
+fn challenges_are_read() {
+    let challenge = challenge();
+    assert_eq!(challenge.time_control, TimeControl::Clock { limit: 600, increment: 5 });
+    assert!(challenge.challenger_is_bot());
+    assert_eq!(challenge.challenger.unwrap().rating, Some(1635));
+
+    let correspondence: Challenge = serde_json::from_str(&CHALLENGE.replace(
+        r#"{"type": "clock", "limit": 600, "increment": 5, "show": "10+5"}"#,
+        r#"{"type": "correspondence", "daysPerTurn": 2}"#,
+    ))
+    .unwrap();
+    assert_eq!(correspondence.time_control, TimeControl::Correspondence { days_per_turn: 2 });
+}
+
+#[test]
+fn default_rules_accept_a_normal_game() {
+    assert_eq!(check_challenge(&ChallengeRules::default(), &challenge(), 0), Ok(()));
+}
+
+#[test]
+fn declines_say_why() {
+    let rules = ChallengeRules::default();
+    let decline = |change: &dyn Fn(&mut Challenge), rules: &ChallengeRules| {
+        let mut challenge = challenge();
+        change(&mut challenge);
+        check_challenge(rules, &challenge, 0).unwrap_err()
+    };
+
+    assert_eq!(decline(&|c| c.variant.key = "chess960".into(), &rules), DeclineReason::Standard);
+    assert_eq!(decline(&|c| c.speed = "ultraBullet".into(), &rules), DeclineReason::TooFast);
+    assert_eq!(decline(&|c| c.speed = "correspondence".into(), &rules), DeclineReason::TooSlow);
+    let fast_clock = |c: &mut Challenge| c.time_control = TimeControl::Clock { limit: 600, increment: 0 };
+    let slow_increments = ChallengeRules { min_increment_secs: 2, ..ChallengeRules::default() };
+    assert_eq!(decline(&fast_clock, &slow_increments), DeclineReason::TooFast);
+
+    let casual_only = ChallengeRules { rated: false, ..ChallengeRules::default() };
+    assert_eq!(decline(&|_| {}, &casual_only), DeclineReason::Casual);
+    let humans_only = ChallengeRules { bots: false, ..ChallengeRules::default() };
+    assert_eq!(decline(&|_| {}, &humans_only), DeclineReason::NoBot);
+    let strong_only = ChallengeRules { min_rating: Some(2000), ..ChallengeRules::default() };
+    assert_eq!(decline(&|_| {}, &strong_only), DeclineReason::Generic);
+}
+
+#[test]
+fn busy_bots_ask_to_come_back_later() {
+    let rules = ChallengeRules { max_games: 2, ..ChallengeRules::default() };
+    assert_eq!(check_challenge(&rules, &challenge(), 1), Ok(()));
+    assert_eq!(check_challenge(&rules, &challenge(), 2), Err(DeclineReason::Later));
+    // But not when the game is one we'd never play anyway.
+    let mut chess960 = challenge();
+    chess960.variant.key = "chess960".into();
+    assert_eq!(check_challenge(&rules, &chess960, 2), Err(DeclineReason::Standard));
+}
@@ -0,0 +1,95 @@
+//! Reading the bot's configuration file.
+use tinychess::config::{BotConfig, ConfigError, LogLevel};
+
+#[test]
+fn example_is_the_defaults() {
+    let example = BotConfig::load("tinychess.example.toml").unwrap();
+    assert_eq!(example, BotConfig::default());
+}
+
+#[test]
+fn missing_settings_keep_their_defaults() {
+    let config = BotConfig::from_toml(
+        r#"
+        [book]
+        randomness = 0.5
+
+        [engine]
+        threads = 2
+
+        [logging]
+        level = "debug"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.book.randomness, 0.5);
+    assert_eq!(config.engine.threads, 2);
+    assert_eq!(config.engine.hash_size_mb, 64);
+    assert_eq!(config.logging.level, LogLevel::Debug);
+}
+
+#[test]
+fn json_works_too() {
+    let config = BotConfig::from_json(r#"{"engine": {"contempt": -10}, "book": {"path": null}}"#).unwrap();
+    assert_eq!(config.engine.contempt, -10);
+    assert_eq!(config.book.path, None);
+}
+
+#[test]
+fn paths_can_be_turned_off() {
+    let config = BotConfig::from_toml(
+        r#"
+        [book]
+        path = ""
+
+        [syzygy]
+        path = ""
+
+        [archive]
+        dir = ""
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.book.path, None);
+    assert_eq!(config.syzygy.path, None);
+    assert_eq!(config.archive.dir, None);
+    config.validate().unwrap();
+
+    let config =
+        BotConfig::from_json(r#"{"book": {"path": null}, "syzygy": {"path": null}, "archive": {"dir": null}}"#).unwrap();
+    assert_eq!(config.book.path, None);
+    assert_eq!(config.syzygy.path, None);
+    assert_eq!(config.archive.dir, None);
+
+    // Left out they stay on
+    let config = BotConfig::from_toml("[book]\nrandomness = 0.5\n").unwrap();
+    assert_eq!(config.book.path.as_deref(), Some("book.bin"));
+    assert_eq!(config.archive.dir.as_deref(), Some("games"));
+    // Tablebases are off until the prober is checked against real tables
+    assert_eq!(config.syzygy.path, None);
+}
+
+#[test]
+fn typos_are_errors() {
+    let result = BotConfig::from_toml("[engine]\nhash_size = 128\n");
+    assert!(matches!(result, Err(ConfigError::Parse(..))), "{result:?}");
+}
+
+#[test]
+fn every_problem_is_reported() {
+    let result = BotConfig::from_toml(
+        r#"
+        [engine]
+        hash_size_mb = 0
+
+        [challenges]
+        variants = ["standard", "chess960"]
+        min_initial_secs = 600
+        max_initial_secs = 60
+        "#,
+    );
+    match result {
+        Err(ConfigError::Invalid(problems)) => assert_eq!(problems.len(), 3, "{problems:?}"),
+        other => panic!("expected validation errors, got {other:?}"),
+    }
+}
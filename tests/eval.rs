@@ -0,0 +1,69 @@
+//! The static evaluation.
+use chess::Board;
+use std::str::FromStr;
+use tinychess::eval::evaluate_board;
+
+/// The same position with the colours swapped: the board turned upside down, white
+/// pieces black and the other side to move.
+fn swap_colors(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let swap_case = |s: &str| -> String {
+        s.chars()
+            .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+            .collect()
+    };
+    let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+    let side = if fields[1] == "w" { "b" } else { "w" };
+    let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+    castling.sort_by_key(|c| (c.is_ascii_lowercase(), "KQkq".find(c.to_ascii_uppercase())));
+    let castling: String = castling.into_iter().collect();
+    let en_passant = match fields[3] {
+        "-" => "-".to_string(),
+        square => square.replace('3', "6").replace('6', "3"),
+    };
+    format!("{} {side} {castling} {en_passant} {} {}", placement.join("/"), fields[4], fields[5])
+}
+
+fn evaluate(fen: &str) -> i32 {
+    evaluate_board(&Board::from_str(fen).unwrap())
+}
+
+#[test]
+fn swapping_colors_negates_the_score() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r1bq1rk1/pp3ppp/2n1pn2/2bp4/2P5/2N1PN2/PPQ1BPPP/R1B2RK1 b - - 0 9",
+        "8/2k5/3p4/p2P1p2/P2P1P2/8/3K4/8 w - - 0 40",
+        "6k1/5ppp/8/3N4/8/1Q6/5PPP/6K1 w - - 0 30",
+    ] {
+        let swapped = swap_colors(fen);
+        assert_eq!(evaluate(&swapped), -evaluate(fen), "{fen} vs {swapped}");
+    }
+    assert_eq!(evaluate("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 0);
+}
+
+#[test]
+fn the_king_centralizes_in_the_endgame() {
+    // Only kings and pawns that don't care where the kings are, the white king on
+    // a1, b2, c3 and d4
+    let scores: Vec<i32> = [
+        "7k/7p/8/8/8/8/7P/K7 w - - 0 1",
+        "7k/7p/8/8/8/8/1K5P/8 w - - 0 1",
+        "7k/7p/8/8/8/2K5/7P/8 w - - 0 1",
+        "7k/7p/8/8/3K4/8/7P/8 w - - 0 1",
+    ]
+    .iter()
+    .map(|fen| evaluate(fen))
+    .collect();
+    assert!(scores.windows(2).all(|pair| pair[0] < pair[1]), "a1 to d4: {scores:?}");
+}
+
+#[test]
+fn the_king_hides_in_the_middlegame() {
+    // Castled, or walked up the board
+    let castled = evaluate("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1");
+    let exposed = evaluate("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2NK1/PPPP1PPP/R1BQ1R2 w - - 0 1");
+    assert_eq!(castled, 0);
+    assert!(exposed < castled - 30, "{exposed}");
+}
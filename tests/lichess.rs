@@ -0,0 +1,78 @@
+//! The Lichess client's rate limiting and retries, against the mock server.
+mod mock_lichess;
+
+use mock_lichess::MockLichess;
+use std::time::{Duration, Instant};
+use tinychess::lichess::{LichessClient, LichessError};
+
+const PAUSE: Duration = Duration::from_millis(300);
+
+fn client(mock: &MockLichess) -> LichessClient {
+    LichessClient::new(&mock.url(), "lip_test").unwrap().with_rate_limit_pause(PAUSE)
+}
+
+fn requests_to(mock: &MockLichess, path: &str) -> Vec<Instant> {
+    mock.requests().iter().filter(|r| r.path == path).map(|r| r.at).collect()
+}
+
+#[tokio::test]
+async fn gets_are_retried_after_the_rate_limit_pause() {
+    let mock = MockLichess::start();
+    let lichess = client(&mock);
+    mock.fail_next("/api/account", 429);
+
+    let account = lichess.account().await.unwrap();
+    assert_eq!(account.id, mock_lichess::ACCOUNT_ID);
+    let tries = requests_to(&mock, "/api/account");
+    assert_eq!(tries.len(), 2);
+    assert!(tries[1] - tries[0] >= PAUSE, "retried after {:?}", tries[1] - tries[0]);
+
+    // Still limited after three tries, and the next request waits out the pause too
+    for _ in 0..3 {
+        mock.fail_next("/api/account", 429);
+    }
+    let result = lichess.account().await;
+    assert!(matches!(&result, Err(LichessError::Status { status, .. }) if status.as_u16() == 429), "{result:?}");
+    lichess.accept_challenge("c1").await.unwrap();
+    let last_try = *requests_to(&mock, "/api/account").last().unwrap();
+    assert!(requests_to(&mock, "/api/challenge/c1/accept")[0] - last_try >= PAUSE);
+}
+
+#[tokio::test]
+async fn posts_that_may_have_gone_through_are_not_resent() {
+    let mock = MockLichess::start();
+    let lichess = client(&mock);
+
+    mock.fail_next("/api/bot/game/g1/move/e2e4", 500);
+    let result = lichess.make_move("g1", "e2e4").await;
+    assert!(matches!(&result, Err(LichessError::Status { status, .. }) if status.as_u16() == 500), "{result:?}");
+    assert_eq!(requests_to(&mock, "/api/bot/game/g1/move/e2e4").len(), 1);
+
+    // A GET is tried again
+    mock.fail_next("/api/account", 500);
+    lichess.account().await.unwrap();
+    assert_eq!(requests_to(&mock, "/api/account").len(), 2);
+
+    // Turned away by the rate limit the move wasn't played, so it is sent again
+    mock.fail_next("/api/bot/game/g1/move/e2e4", 429);
+    lichess.make_move("g1", "e2e4").await.unwrap();
+    assert_eq!(requests_to(&mock, "/api/bot/game/g1/move/e2e4").len(), 3);
+}
+
+#[tokio::test]
+async fn requests_go_out_one_at_a_time() {
+    let mock = MockLichess::start();
+    let lichess = client(&mock);
+    mock.set_delay(Duration::from_millis(100));
+
+    let (account, accepted, moved) = tokio::join!(
+        lichess.account(),
+        lichess.accept_challenge("c1"),
+        lichess.make_move("g1", "e2e4"),
+    );
+    account.unwrap();
+    accepted.unwrap();
+    moved.unwrap();
+    assert_eq!(mock.requests().len(), 3);
+    assert_eq!(mock.max_in_flight(), 1);
+}
@@ -0,0 +1,242 @@
+//! A stand-in for lichess.org that the bot binary talks to in tests. It answers the
+//! endpoints the bot uses, streams the lines a test sends it, and records every
+//! request so the test can wait for the bot's answers.
+// Every test file uses a different part of it.
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The id of the bot account, /api/account says it is "TinyBot".
+pub const ACCOUNT_ID: &str = "tinybot";
+
+// Long enough for a debug build of the engine to find its move.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+    /// When the request came in.
+    pub at: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<Recorded>,
+    // The lines of every streaming endpoint by path. The receiver is taken by the
+    // connection that streams them.
+    streams: HashMap<String, (Sender<String>, Option<Receiver<String>>)>,
+    // Statuses to answer instead of 200, each for the next request to its path.
+    statuses: Vec<(String, u16)>,
+    // How long every answer but a stream takes.
+    delay: Duration,
+    // Requests being answered right now, and the most there ever were.
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+impl State {
+    fn stream(&mut self, path: &str) -> &mut (Sender<String>, Option<Receiver<String>>) {
+        self.streams.entry(path.to_string()).or_insert_with(|| {
+            let (sender, receiver) = channel();
+            (sender, Some(receiver))
+        })
+    }
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+pub struct MockLichess {
+    addr: SocketAddr,
+    state: Shared,
+}
+
+impl MockLichess {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state: Shared = Arc::default();
+        let shared = Arc::clone(&state);
+        // Lives as long as the test process, like the threads of the streams
+        thread::spawn(move || {
+            for connection in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                // Streams never finish, so every request gets its own thread
+                thread::spawn(move || handle(connection, &state));
+            }
+        });
+        MockLichess { addr, state }
+    }
+
+    /// What `lichess.base_url` has to be.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Send a line on the event stream, now or as soon as the bot connects.
+    pub fn send_event(&self, line: &str) {
+        self.send("/api/stream/event", line);
+    }
+
+    /// Send a line on the stream of a game, now or as soon as the bot connects.
+    pub fn send_game(&self, game_id: &str, line: &str) {
+        self.send(&format!("/api/bot/game/stream/{game_id}"), line);
+    }
+
+    /// Drop the connection to the stream of a game, like a network hiccup would.
+    /// Lines sent afterwards go to the next connection.
+    pub fn disconnect_game(&self, game_id: &str) {
+        self.disconnect(&format!("/api/bot/game/stream/{game_id}"));
+    }
+
+    /// Drop the connection to the event stream, see `disconnect_game`.
+    pub fn disconnect_events(&self) {
+        self.disconnect("/api/stream/event");
+    }
+
+    fn disconnect(&self, path: &str) {
+        let mut state = self.state.0.lock().unwrap();
+        let (sender, receiver) = channel();
+        *state.stream(path) = (sender, Some(receiver));
+    }
+
+    fn send(&self, path: &str, line: &str) {
+        let mut state = self.state.0.lock().unwrap();
+        // Nobody listens if the bot hung up, like on the real thing
+        let _ = state.stream(path).0.send(line.to_string());
+    }
+
+    /// Answer the next request to `path` with `status` instead of 200. Several calls
+    /// for the same path answer the requests after it in order.
+    pub fn fail_next(&self, path: &str, status: u16) {
+        self.state.0.lock().unwrap().statuses.push((path.to_string(), status));
+    }
+
+    /// Take `delay` to answer every request that isn't a stream.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.0.lock().unwrap().delay = delay;
+    }
+
+    /// The most requests (streams aside) that were ever being answered at once.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.0.lock().unwrap().max_in_flight
+    }
+
+    /// Wait for the first request `matches`, panicking with `what` if the bot never sends it.
+    pub fn wait_for(&self, what: &str, matches: impl Fn(&Recorded) -> bool) -> Recorded {
+        let (lock, changed) = &*self.state;
+        let deadline = Instant::now() + TIMEOUT;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(request) = state.requests.iter().find(|r| matches(r)) {
+                return request.clone();
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                panic!("timed out waiting for {what}, requests so far: {:#?}", state.requests);
+            }
+            state = changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Every request so far, oldest first.
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.0.lock().unwrap().requests.clone()
+    }
+}
+
+// One request per connection, which is closed after the answer, so a stream
+// ending really drops the connection.
+fn handle(mut connection: TcpStream, state: &Shared) {
+    let Some((method, path, body)) = read_request(&mut connection) else { return };
+    let at = Instant::now();
+    let (lines, status, delay) = {
+        let (lock, changed) = &**state;
+        let mut state = lock.lock().unwrap();
+        state.requests.push(Recorded { method, path: path.clone(), body, at });
+        changed.notify_all();
+        let status = match state.statuses.iter().position(|(p, _)| *p == path) {
+            Some(i) => state.statuses.remove(i).1,
+            None => 200,
+        };
+        let streaming = status == 200 && (path == "/api/stream/event" || path.starts_with("/api/bot/game/stream/"));
+        if !streaming {
+            state.in_flight += 1;
+            state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        }
+        let lines = streaming.then(|| {
+            let stream = state.stream(&path);
+            // A second connection to the same stream gets whatever is sent from now on
+            stream.1.take().unwrap_or_else(|| {
+                let (sender, receiver) = channel();
+                stream.0 = sender;
+                receiver
+            })
+        });
+        (lines, status, state.delay)
+    };
+
+    match lines {
+        Some(lines) => {
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n";
+            if connection.write_all(head.as_bytes()).is_err() {
+                return;
+            }
+            for line in lines {
+                if writeln!(connection, "{line}").is_err() {
+                    return;
+                }
+            }
+        }
+        None => {
+            thread::sleep(delay);
+            // Done before answering, the client may send its next request as soon as it has the answer
+            state.0.lock().unwrap().in_flight -= 1;
+            let (reason, json) = match (status, path.as_str()) {
+                (200, "/api/account") => ("OK", r#"{"id":"tinybot","username":"TinyBot","title":"BOT"}"#),
+                (200, _) => ("OK", r#"{"ok":true}"#),
+                (429, _) => ("Too Many Requests", r#"{"error":"Too many requests. Try again later."}"#),
+                _ => ("Error", r#"{"error":"Something went wrong"}"#),
+            };
+            let _ = write!(
+                connection,
+                "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{json}",
+                json.len()
+            );
+        }
+    }
+    let _ = connection.shutdown(Shutdown::Both);
+}
+
+/// The method, path and body of an HTTP/1.1 request.
+fn read_request(connection: &mut TcpStream) -> Option<(String, String, String)> {
+    let mut reader = BufReader::new(connection);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next()?.to_string(), parts.next()?.to_string());
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some((method, path, String::from_utf8(body).ok()?))
+}
@@ -0,0 +1,123 @@
+//! The standard perft positions from the Chess Programming Wiki, plus checks that
+//! boards rebuilt from UCI move strings (the way we follow Lichess games) match.
+use chess::{Board, MoveGen};
+use std::str::FromStr;
+use tinychess::notation::{
+    apply_uci_moves, format_move_as_san, format_move_as_uci, parse_san, parse_uci_move, MoveError,
+};
+use tinychess::perft::{divide, perft};
+use tinychess::GameHistory;
+
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSITION_6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+fn check(fen: &str, expected: &[u64]) {
+    let board = Board::from_str(fen).unwrap();
+    for (depth, &nodes) in (1..).zip(expected) {
+        assert_eq!(perft(&board, depth), nodes, "perft({depth}) of {fen}");
+    }
+}
+
+#[test]
+fn startpos() {
+    check(STARTPOS, &[20, 400, 8_902, 197_281]);
+}
+
+#[test]
+fn kiwipete() {
+    check(KIWIPETE, &[48, 2_039, 97_862]);
+}
+
+#[test]
+fn position_3() {
+    check(POSITION_3, &[14, 191, 2_812, 43_238]);
+}
+
+#[test]
+fn position_4() {
+    check(POSITION_4, &[6, 264, 9_467]);
+}
+
+#[test]
+fn position_5() {
+    check(POSITION_5, &[44, 1_486, 62_379]);
+}
+
+#[test]
+fn position_6() {
+    check(POSITION_6, &[46, 2_079, 89_890]);
+}
+
+#[test]
+fn divide_adds_up_to_perft() {
+    let board = Board::from_str(KIWIPETE).unwrap();
+    let moves = divide(&board, 3);
+    assert_eq!(moves.len(), 48);
+    assert_eq!(moves.iter().map(|(_, nodes)| nodes).sum::<u64>(), 97_862);
+}
+
+/// Every move sequence of `depth` plies, replayed from `fen` as a UCI move string,
+/// must give the same board as making the moves directly.
+fn check_uci_replay(fen: &str, depth: u32) {
+    fn walk(start: &Board, board: &Board, line: &mut Vec<String>, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+        for mv in MoveGen::new_legal(board) {
+            let uci = format_move_as_uci(mv);
+            assert_eq!(parse_uci_move(board, &uci), Ok(mv), "{uci} after {line:?}");
+            line.push(uci);
+
+            let expected = board.make_move_new(mv);
+            let mut rebuilt = *start;
+            apply_uci_moves(&mut rebuilt, &mut GameHistory::default(), &line.join(" ")).unwrap();
+            assert_eq!(rebuilt, expected, "board after {line:?}");
+
+            walk(start, &expected, line, depth - 1);
+            line.pop();
+        }
+    }
+    let start = Board::from_str(fen).unwrap();
+    walk(&start, &start, &mut Vec::new(), depth);
+}
+
+#[test]
+fn uci_replay_matches_move_generation() {
+    // Castling, en passant, promotions (and under-promotions) and pins between them.
+    for fen in [STARTPOS, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6] {
+        check_uci_replay(fen, 2);
+    }
+}
+
+#[test]
+fn replay_stops_at_a_bad_move() {
+    let mut board = Board::default();
+    let result = apply_uci_moves(&mut board, &mut GameHistory::default(), "e2e4 e7e5 e4e5 d2d4");
+    assert_eq!(result, Err(MoveError::Illegal("e4e5".to_string())));
+    assert_eq!(board, Board::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap());
+
+    let mut board = Board::default();
+    let result = apply_uci_moves(&mut board, &mut GameHistory::default(), "e2e4 O-O");
+    assert_eq!(result, Err(MoveError::Malformed("O-O".to_string())));
+}
+
+#[test]
+fn san_round_trips() {
+    // Every move at depth 1 and 2, so disambiguation and promotions get covered too.
+    for fen in [STARTPOS, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6] {
+        let board = Board::from_str(fen).unwrap();
+        for first in MoveGen::new_legal(&board) {
+            let after = board.make_move_new(first);
+            for (board, mv) in std::iter::once((board, first))
+                .chain(MoveGen::new_legal(&after).map(|mv| (after, mv)))
+            {
+                let san = format_move_as_san(&board, mv);
+                assert_eq!(parse_san(&board, &san), Ok(mv), "{san} in {board}");
+            }
+        }
+    }
+}
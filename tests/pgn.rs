@@ -0,0 +1,117 @@
+//! Writing games as PGN and reading them back.
+use chess::Board;
+use std::str::FromStr;
+use tinychess::notation::parse_san;
+use std::time::Duration;
+use tinychess::pgn::{format_date, move_time_comment, parse_pgn, PgnError, PgnGame};
+
+fn play(game: &mut PgnGame, moves: &str) {
+    for san in moves.split_whitespace() {
+        let mv = parse_san(&game.board(), san).unwrap();
+        game.push(mv, None);
+    }
+}
+
+#[test]
+fn written_games_read_back() {
+    let mut game = PgnGame::default();
+    game.set_tag("White", "tinychess");
+    game.set_tag("Black", "Some \"quoted\" name");
+    game.set_tag("WhiteElo", "1500");
+    // The Opera game, long enough to be wrapped.
+    play(&mut game, "e4 e5 Nf3 d6 d4 Bg4 dxe5 Bxf3 Qxf3 dxe5 Bc4 Nf6 Qb3 Qe7 Nc3 c6 Bg5 b5");
+    game.moves[1].comment = Some("[%eval 0.25,10]".to_string());
+    game.moves[4].comment = Some("book".to_string());
+    play(&mut game, "Nxb5 cxb5 Bxb5+ Nbd7 O-O-O Rd8 Rxd7 Rxd7 Rd1 Qe6 Bxd7+ Nxd7 Qb8+ Nxb8 Rd8#");
+    game.set_tag("Result", "1-0");
+
+    let pgn = game.to_string();
+    assert!(pgn.contains("[Black \"Some \\\"quoted\\\" name\"]"));
+    assert!(pgn.contains("1. e4 e5 {[%eval 0.25,10]} 2. Nf3 d6 3. d4 {book} 3... Bg4"));
+    assert!(pgn.contains("17. Rd8# 1-0"));
+    assert!(pgn.lines().all(|line| line.len() < 80));
+    assert_eq!(PgnGame::from_str(&pgn), Ok(game));
+}
+
+#[test]
+fn games_from_a_position() {
+    let start = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+    let mut game = PgnGame::new(start);
+    play(&mut game, "Kd7 e4 Ke6");
+    let pgn = game.to_string();
+    assert!(pgn.contains("[SetUp \"1\"]"));
+    assert!(pgn.contains("1... Kd7 2. e4 Ke6 *"));
+    let read = PgnGame::from_str(&pgn).unwrap();
+    assert_eq!(read.start, start);
+    assert_eq!(read.board(), game.board());
+}
+
+#[test]
+fn games_from_a_position_read_back() {
+    // With the FEN tag set, like the bot does, the move number is kept
+    let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 3 42";
+    let mut game = PgnGame::new(Board::from_str(fen).unwrap());
+    game.set_tag("SetUp", "1");
+    game.set_tag("FEN", fen);
+    play(&mut game, "Kd7 e4 Ke6");
+    let pgn = game.to_string();
+    assert_eq!(pgn.matches("[FEN ").count(), 1);
+    assert!(pgn.contains("42... Kd7 43. e4 Ke6 *"));
+    assert_eq!(PgnGame::from_str(&pgn), Ok(game));
+}
+
+#[test]
+fn other_programs_pgn() {
+    let text = r#"
+% An escaped line
+[Event "Casual game"]
+[Site "https://lichess.org/abcdefgh"]
+[Result "*"]
+
+1.e4 c5 2. Nf3 $1 (2. Nc3 Nc6 (2... d6) 3. f4) 2...d6 { Najdorf
+coming } 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6!? ; end of the opening
+0-1
+
+[Event "Second"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 w Q - 0 40"]
+
+40. O-O-O 1/2-1/2
+
+[Event "Broken"]
+
+1. e4 e4 2. Nf3 *
+
+[Event "No result"]
+
+1. d4 d5
+"#;
+    let games = parse_pgn(text);
+    assert_eq!(games.len(), 4);
+
+    let first = games[0].as_ref().unwrap();
+    assert_eq!(first.moves.len(), 10);
+    assert_eq!(first.tag("Site"), Some("https://lichess.org/abcdefgh"));
+    assert_eq!(first.result(), "0-1");
+    assert_eq!(first.moves[3].comment.as_deref(), Some("Najdorf\ncoming"));
+    assert_eq!(first.moves[9].comment.as_deref(), Some("end of the opening"));
+
+    let second = games[1].as_ref().unwrap();
+    assert_eq!(second.moves.len(), 1);
+    assert_eq!(second.result(), "1/2-1/2");
+
+    assert!(matches!(games[2], Err(PgnError::InvalidMove { ply: 1, .. })));
+    assert_eq!(games[3].as_ref().unwrap().moves.len(), 2);
+}
+
+#[test]
+fn dates() {
+    assert_eq!(format_date(0), "1970.01.01");
+    assert_eq!(format_date(1_523_825_103_562), "2018.04.15");
+    assert_eq!(format_date(1_709_251_199_000), "2024.02.29");
+}
+
+#[test]
+fn move_times() {
+    assert_eq!(move_time_comment(Duration::from_millis(1_254)), "[%emt 0:00:01.25]");
+    assert_eq!(move_time_comment(Duration::from_secs(3_725)), "[%emt 1:02:05.00]");
+}
@@ -0,0 +1,180 @@
+//! Draws by repetition and the fifty-move rule, searches in the background, and pondering.
+use chess::Board;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tinychess::notation::{format_move_as_uci, parse_uci_move};
+use tinychess::{Engine, EngineConfig, GameHistory, PonderHit, SearchInfo, SearchLimits, TimeBudget};
+
+fn engine(contempt: i32) -> Engine {
+    Engine::new(EngineConfig {
+        hash_size_mb: 1,
+        threads: 1,
+        contempt,
+    })
+}
+
+/// `fen` and then `moves`, the way a game would have got there.
+fn play(fen: &str, moves: &str) -> (Board, GameHistory) {
+    let mut board = Board::from_str(fen).unwrap();
+    let mut history = GameHistory::default();
+    for uci in moves.split_whitespace() {
+        let mv = parse_uci_move(&board, uci).unwrap();
+        history.push(&board, mv);
+        board = board.make_move_new(mv);
+    }
+    (board, history)
+}
+
+fn search(engine: &Engine, board: &Board, history: &GameHistory) -> SearchInfo {
+    let limits = SearchLimits {
+        max_depth: 4,
+        ..Default::default()
+    };
+    engine.search(board, history, &limits, &AtomicBool::new(false), |_| {}).unwrap()
+}
+
+// The knight went out and came back while the black king did the same, twice, so
+// g1f3 brings about the position after the first move a third time.
+const SHUFFLE: &str = "g1f3 a8b8 f3g1 b8a8 g1f3 a8b8 f3g1 b8a8";
+
+#[test]
+fn the_side_behind_repeats() {
+    // A knight against a queen
+    let (board, history) = play("k7/2q5/8/8/8/8/8/6NK w - - 0 1", SHUFFLE);
+    let info = search(&engine(0), &board, &history);
+    assert_eq!(format_move_as_uci(info.best_move), "g1f3");
+    assert_eq!(info.score, 0);
+
+    // Without the history it is just lost
+    let info = search(&engine(0), &board, &GameHistory::default());
+    assert!(info.score < -500, "{}", info.score);
+}
+
+#[test]
+fn a_second_time_in_the_game_is_no_draw() {
+    // g1f3 would only repeat the position after the first move once
+    let (board, history) = play("k7/2q5/8/8/8/8/8/6NK w - - 0 1", "g1f3 a8b8 f3g1 b8a8");
+    let info = search(&engine(0), &board, &history);
+    assert!(info.score < -500, "{}", info.score);
+
+    // nor does it get a side that likes draws one
+    let (board, history) = play("k7/2n4p/8/8/8/8/P7/6NK w - - 0 1", "g1f3 a8b8 f3g1 b8a8");
+    let info = search(&engine(-200), &board, &history);
+    assert!(info.score < 100, "{}", info.score);
+}
+
+#[test]
+fn contempt_avoids_repeating() {
+    for (fen, what) in [
+        ("k7/8/8/7Q/8/8/8/6NK w - - 0 1", "ahead"),
+        ("k7/2n4p/8/8/8/8/P7/6NK w - - 0 1", "equal"),
+    ] {
+        let (board, history) = play(fen, SHUFFLE);
+        let info = search(&engine(50), &board, &history);
+        assert_ne!(format_move_as_uci(info.best_move), "g1f3", "repeated when {what}");
+        assert!(info.score > -50, "{what}: {}", info.score);
+    }
+
+    // An equal side with a taste for draws takes one
+    let (board, history) = play("k7/2n4p/8/8/8/8/P7/6NK w - - 0 1", SHUFFLE);
+    let info = search(&engine(-200), &board, &history);
+    assert_eq!(format_move_as_uci(info.best_move), "g1f3");
+    assert_eq!(info.score, 200);
+}
+
+#[test]
+fn the_fifty_move_rule_is_a_draw() {
+    // Winning, with no mate in one
+    let board = Board::from_str("8/8/8/4k3/8/8/Q7/4K3 w - - 0 80").unwrap();
+    let fresh = search(&engine(0), &board, &GameHistory::default());
+    assert!(fresh.score > 500, "{}", fresh.score);
+
+    // Nothing resets the clock, so every move ends the game
+    for halfmove_clock in [99, 100, 120] {
+        let history = GameHistory {
+            hashes: Vec::new(),
+            halfmove_clock,
+        };
+        let info = search(&engine(0), &board, &history);
+        assert_eq!(info.score, 0, "with {halfmove_clock} halfmoves");
+    }
+}
+
+#[test]
+fn pondering_waits_for_the_hit() {
+    let engine = Arc::new(engine(0));
+    let hit = Arc::new(PonderHit::default());
+    let limits = SearchLimits {
+        time: Some(TimeBudget::fixed(Duration::from_millis(50))),
+        ponder: Some(Arc::clone(&hit)),
+        ..Default::default()
+    };
+    let handle = engine.start_search(Board::default(), GameHistory::default(), limits, |_| {});
+
+    // Far past the budget, but it doesn't count yet
+    thread::sleep(Duration::from_millis(300));
+    assert!(!handle.is_finished());
+
+    let start = Instant::now();
+    hit.hit();
+    let info = handle.wait().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2), "took {:?} after the hit", start.elapsed());
+    assert!(info.depth > 0);
+}
+
+#[test]
+fn stopping_keeps_the_best_move_so_far() {
+    let engine = Arc::new(engine(0));
+    let board = Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+    let (sender, depths) = std::sync::mpsc::channel();
+    // No limits at all, only a stop ends it
+    let handle = engine.start_search(board, GameHistory::default(), SearchLimits::default(), move |info| {
+        let _ = sender.send(info.depth);
+    });
+    while depths.recv_timeout(Duration::from_secs(30)).unwrap() < 3 {}
+    assert!(!handle.is_finished());
+
+    let start = Instant::now();
+    handle.stop();
+    let info = handle.wait().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2), "took {:?} to stop", start.elapsed());
+    assert!(info.depth >= 3);
+    assert!(board.legal(info.best_move));
+
+    // Even before the first iteration is done there is some move
+    let handle = engine.start_search(board, GameHistory::default(), SearchLimits::default(), |_| {});
+    handle.stop();
+    assert!(board.legal(handle.wait().unwrap().best_move));
+}
+
+#[test]
+fn the_pv_is_a_legal_line_from_the_best_move() {
+    let engine = engine(0);
+    // Cut short by nodes, which a debug build gets through quickly enough
+    let limits = SearchLimits {
+        max_nodes: Some(20_000),
+        ..Default::default()
+    };
+    let mut longest = 0;
+    for fen in tinychess::bench::BENCH_POSITIONS.iter().step_by(5) {
+        let board = Board::from_str(fen).unwrap();
+        let info = engine.search(&board, &GameHistory::default(), &limits, &AtomicBool::new(false), |_| {}).unwrap();
+        assert_eq!(info.pv.first(), Some(&info.best_move), "{fen}");
+        let mut position = board;
+        for &mv in &info.pv {
+            assert!(position.legal(mv), "{fen}: {} is not legal in the pv {:?}", format_move_as_uci(mv), info.pv);
+            position = position.make_move_new(mv);
+        }
+        longest = longest.max(info.pv.len());
+    }
+    assert!(longest >= 3, "only single moves");
+
+    // A mate ends the line
+    let board = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    let info = search(&engine, &board, &GameHistory::default());
+    assert_eq!(info.pv.len(), 1);
+    assert_eq!(board.make_move_new(info.pv[0]).status(), chess::BoardStatus::Checkmate);
+}
@@ -0,0 +1,94 @@
+//! Adjudication of self-play games and the match statistics.
+use chess::Board;
+use std::str::FromStr;
+use tinychess::selfplay::{play_game, MatchScore, Outcome, Sprt, SprtResult, Termination};
+use tinychess::{Engine, EngineConfig, SearchLimits};
+
+fn engine() -> Engine {
+    Engine::new(EngineConfig {
+        hash_size_mb: 1,
+        threads: 1,
+        contempt: 0,
+    })
+}
+
+#[test]
+fn games_end_in_mate() {
+    let engine = engine();
+    let limits = SearchLimits {
+        max_depth: 2,
+        ..Default::default()
+    };
+    // Back rank mate in one for White.
+    let board = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    let (moves, outcome, termination) =
+        play_game((&engine, &limits), (&engine, &limits), &board, 100);
+    assert_eq!(moves.len(), 1);
+    assert_eq!(outcome, Outcome::WhiteWins);
+    assert_eq!(termination, Termination::Checkmate);
+}
+
+#[test]
+fn dead_positions_are_draws() {
+    let engine = engine();
+    let limits = SearchLimits {
+        max_depth: 1,
+        ..Default::default()
+    };
+    for fen in [
+        "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+        "8/8/4k3/8/8/3K1N2/8/8 w - - 0 1",
+        "8/2b5/4k3/8/8/3K2B1/8/8 w - - 0 1",
+    ] {
+        let board = Board::from_str(fen).unwrap();
+        let (moves, outcome, termination) =
+            play_game((&engine, &limits), (&engine, &limits), &board, 100);
+        assert!(moves.is_empty(), "{fen}");
+        assert_eq!(outcome, Outcome::Draw);
+        assert_eq!(termination, Termination::InsufficientMaterial);
+    }
+}
+
+#[test]
+fn elo_of_known_scores() {
+    let even = MatchScore {
+        wins: 30,
+        draws: 40,
+        losses: 30,
+    };
+    assert_eq!(even.elo().0, 0.0);
+    // 75% is about 191 Elo.
+    let ahead = MatchScore {
+        wins: 60,
+        draws: 30,
+        losses: 10,
+    };
+    assert!((ahead.elo().0 - 190.85).abs() < 0.1);
+    assert!(ahead.elo().1 > 0.0 && ahead.elo().1 < 100.0);
+}
+
+#[test]
+fn sprt_decides_clear_results() {
+    let sprt = Sprt::default();
+    let (lower, upper) = sprt.bounds();
+    assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+
+    let winning = MatchScore {
+        wins: 600,
+        draws: 800,
+        losses: 400,
+    };
+    assert_eq!(sprt.result(&winning), SprtResult::H1);
+    let losing = MatchScore {
+        wins: 400,
+        draws: 800,
+        losses: 600,
+    };
+    assert_eq!(sprt.result(&losing), SprtResult::H0);
+    let unclear = MatchScore {
+        wins: 10,
+        draws: 10,
+        losses: 10,
+    };
+    assert_eq!(sprt.result(&unclear), SprtResult::Continue);
+}
@@ -0,0 +1,50 @@
+//! Probing Syzygy tables.
+use chess::{Board, ChessMove, Square};
+use std::path::Path;
+use std::str::FromStr;
+use tinychess::syzygy::{Tablebases, Wdl};
+
+const TABLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+
+#[test]
+fn nothing_is_found_without_tables() {
+    let dir = std::env::temp_dir().join(format!("tinychess-no-tables-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let tablebases = Tablebases::open(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(tablebases.is_empty());
+    assert_eq!(tablebases.max_pieces(), 0);
+    let board = Board::from_str("6k1/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+    assert_eq!(tablebases.probe_wdl(&board), None);
+    assert_eq!(tablebases.probe_root(&board, 0), None);
+}
+
+// The KRvK files aren't in the repository, they are at https://tablebase.lichess.ovh/tables/standard/3-4-5/
+#[test]
+#[ignore = "needs KRvK.rtbw and KRvK.rtbz in tests/syzygy"]
+fn krvk() {
+    assert!(Path::new(TABLES).join("KRvK.rtbw").exists(), "no KRvK tables in {TABLES}");
+    let tablebases = Tablebases::open(TABLES).unwrap();
+    assert_eq!(tablebases.max_pieces(), 3);
+
+    let positions = [
+        // Mate in one, for either colour
+        ("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", Wdl::Win, 1),
+        ("r7/8/8/8/8/6k1/8/6K1 b - - 0 1", Wdl::Win, 1),
+        // Kg8 is the only move, and Ra8 mates
+        ("7k/8/6K1/8/8/8/8/R7 b - - 0 1", Wdl::Loss, -2),
+        // The rook hangs
+        ("8/8/8/8/8/8/1k6/R6K b - - 0 1", Wdl::Draw, 0),
+    ];
+    for (fen, wdl, dtz) in positions {
+        let board = Board::from_str(fen).unwrap();
+        assert_eq!(tablebases.probe_wdl(&board), Some(wdl), "{fen}");
+        assert_eq!(tablebases.probe_dtz(&board), Some(dtz), "{fen}");
+    }
+
+    let board = Board::from_str("6k1/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+    let root = tablebases.probe_root(&board, 0).unwrap();
+    assert_eq!(root.moves, [ChessMove::new(Square::A1, Square::A8, None)]);
+    assert_eq!((root.wdl, root.dtz), (Wdl::Win, 1));
+}
@@ -0,0 +1,68 @@
+//! Splitting the clock into time budgets per move.
+use std::time::Duration;
+use tinychess::{Clock, TimeBudget, TimeManager};
+
+const OVERHEAD: Duration = Duration::from_millis(50);
+
+fn allocate(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>, moves_played: u32) -> TimeBudget {
+    let clock = Clock {
+        remaining: Duration::from_millis(remaining_ms),
+        increment: Duration::from_millis(increment_ms),
+        moves_to_go,
+    };
+    let budget = TimeManager::new(OVERHEAD).allocate(&clock, moves_played);
+    // Whatever the increment, a move never takes the whole clock
+    let usable = clock.remaining.saturating_sub(OVERHEAD).max(Duration::from_millis(1));
+    assert!(budget.soft <= budget.hard, "{budget:?}");
+    assert!(budget.soft <= usable / 2, "{budget:?} with {clock:?}");
+    assert!(budget.hard <= usable * 3 / 4, "{budget:?} with {clock:?}");
+    budget
+}
+
+#[test]
+fn sudden_death() {
+    // 50 moves to go at the start, 60s - 50ms of overhead
+    let budget = allocate(60_000, 0, None, 0);
+    assert_eq!(budget.soft, Duration::from_micros(1_199_000));
+    assert_eq!(budget.hard, budget.soft * 4);
+
+    // Moves played are full moves, 20 to go after 30 of them
+    assert_eq!(allocate(60_000, 0, None, 30).soft, Duration::from_micros(2_997_500));
+    // and never fewer than that
+    assert_eq!(allocate(60_000, 0, None, 80).soft, Duration::from_micros(2_997_500));
+}
+
+#[test]
+fn increments_are_mostly_spent() {
+    let budget = allocate(10_000, 5_000, None, 0);
+    assert_eq!(budget.soft, Duration::from_millis(199) + Duration::from_millis(3_750));
+    // A quarter of the clock plus the increment would be more
+    assert_eq!(budget.hard, Duration::from_micros(7_462_500));
+}
+
+#[test]
+fn an_increment_bigger_than_the_clock_does_not_spend_it_all() {
+    let budget = allocate(1_000, 2_000, None, 10);
+    assert_eq!(budget.soft, Duration::from_millis(475));
+    assert_eq!(budget.hard, Duration::from_micros(712_500));
+}
+
+#[test]
+fn the_last_move_before_the_time_control() {
+    let budget = allocate(10_000, 0, Some(1), 39);
+    assert_eq!(budget.soft, Duration::from_micros(4_975_000));
+    assert_eq!(budget.hard, Duration::from_micros(7_462_500));
+    // With more moves to go they share the clock
+    assert_eq!(allocate(10_000, 0, Some(10), 30).soft, Duration::from_millis(995));
+}
+
+#[test]
+fn a_nearly_empty_clock() {
+    // Less than the overhead left, still a little time to find some move
+    let budget = allocate(30, 0, None, 60);
+    assert!(budget.soft > Duration::ZERO);
+    assert!(budget.hard < Duration::from_millis(1));
+
+    let budget = allocate(0, 1_000, Some(1), 0);
+    assert!(budget.hard < Duration::from_millis(1));
+}
@@ -0,0 +1,157 @@
+//! The UCI frontend, driven through its stdin and stdout like a GUI would.
+use chess::Board;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use tinychess::notation::parse_uci_move;
+
+// Long enough for a debug build to search a few plies.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The uci binary, told to quit and killed when the test is done with it.
+struct Engine {
+    process: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn start() -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_uci"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = Engine { process, stdin, lines };
+        engine.send("uci");
+        engine.expect("uciok", TIMEOUT);
+        engine
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.stdin, "{command}").unwrap();
+    }
+
+    /// Everything the engine says up to and including the first line starting with
+    /// `prefix`.
+    fn expect(&self, prefix: &str, timeout: Duration) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => {
+                    let done = line.starts_with(prefix);
+                    lines.push(line);
+                    if done {
+                        return lines;
+                    }
+                }
+                Err(_) => panic!("no {prefix:?} within {timeout:?}, got {lines:#?}"),
+            }
+        }
+    }
+
+    /// The move of the `bestmove` line that ends a search.
+    fn best_move(&self, timeout: Duration) -> String {
+        let lines = self.expect("bestmove", timeout);
+        lines.last().unwrap().split_whitespace().nth(1).unwrap().to_string()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn board_after(fen: &str, moves: &str) -> Board {
+    moves.split_whitespace().fold(Board::from_str(fen).unwrap(), |board, uci| {
+        board.make_move_new(parse_uci_move(&board, uci).unwrap())
+    })
+}
+
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+fn handshake() {
+    let mut engine = Engine::start();
+    engine.send("isready");
+    assert_eq!(engine.expect("readyok", TIMEOUT), ["readyok"]);
+    engine.send("setoption name Hash value 1");
+    engine.send("setoption name Threads value 1");
+    engine.send("ucinewgame");
+    engine.send("isready");
+    assert_eq!(engine.expect("readyok", TIMEOUT), ["readyok"]);
+}
+
+#[test]
+fn searches_the_position_it_is_given() {
+    let mut engine = Engine::start();
+    engine.send("setoption name Threads value 1");
+
+    let moves = "e2e4 e7e5 g1f3";
+    engine.send(&format!("position startpos moves {moves}"));
+    engine.send("go depth 3");
+    let best = engine.best_move(TIMEOUT);
+    assert!(parse_uci_move(&board_after(STARTPOS, moves), &best).is_ok(), "{best} after {moves}");
+    // Stopping a search that is already over doesn't answer again
+    engine.send("stop");
+    engine.send("isready");
+    assert_eq!(engine.expect("readyok", TIMEOUT), ["readyok"]);
+
+    // Back rank mate, after a few moves from a FEN
+    engine.send("position fen 6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 1 moves g8h8 g1f1 h8g8");
+    engine.send("go depth 2");
+    assert_eq!(engine.best_move(TIMEOUT), "a1a8");
+
+    // Searches also end on a node limit
+    engine.send("position startpos");
+    engine.send("go nodes 500");
+    let best = engine.best_move(TIMEOUT);
+    assert!(parse_uci_move(&Board::default(), &best).is_ok(), "{best}");
+}
+
+#[test]
+fn stop_ends_an_infinite_search() {
+    let mut engine = Engine::start();
+    engine.send("setoption name Threads value 1");
+    engine.send("position startpos moves d2d4 g8f6");
+    engine.send("go infinite");
+    engine.expect("info depth 2", TIMEOUT);
+
+    // However deep it got, it waits to be told
+    thread::sleep(Duration::from_millis(300));
+    engine.send("isready");
+    let lines = engine.expect("readyok", TIMEOUT);
+    assert!(!lines.iter().any(|line| line.starts_with("bestmove")), "{lines:#?}");
+
+    engine.send("stop");
+    let best = engine.best_move(Duration::from_secs(2));
+    assert!(parse_uci_move(&board_after(STARTPOS, "d2d4 g8f6"), &best).is_ok(), "{best}");
+}
+
+#[test]
+fn a_negative_clock_still_moves_at_once() {
+    let mut engine = Engine::start();
+    engine.send("setoption name Threads value 1");
+    engine.send("position startpos");
+    // White is over its time, which some GUIs send as a negative clock
+    engine.send("go wtime -100 btime 1000 winc 0 binc 0");
+    let best = engine.best_move(Duration::from_secs(2));
+    assert!(parse_uci_move(&Board::default(), &best).is_ok(), "{best}");
+}